@@ -1,14 +1,82 @@
+use std::collections::BTreeMap;
 use std::process::Command;
+use std::time::{Duration, Instant};
 
-use chrono::{Local, NaiveDate};
+use chrono::{DateTime, Local, NaiveDate};
 
+use crate::config::Config;
 use crate::data::{
-    get_archive_path_for_date, get_csv_path_for_date, list_available_dates, parse_csv,
-    parse_csv_from_line, BatteryRecord,
+    BatteryRecord, EventKind, HealthRecord, LogData, LogEvent, ProcessRecord, battery_names,
+    combine_batteries, get_archive_path_for_date, get_csv_path_for_date, get_health_log_path,
+    get_process_log_path, list_available_dates, parse_csv, parse_csv_from_line, read_health_log,
+    read_process_log,
 };
+use crate::ipc::{Subscription, get_socket_path};
 
-const SLEEP_THRESHOLD_SECS: i64 = 10 * 60;
-const MAX_SLEEP_DRAIN_RATE_PER_HOUR: f64 = 5.0;
+/// Drain rate (%/h) at or below which a long gap on battery looks like
+/// hibernation, where only self-discharge remains, rather than suspend-to-RAM.
+const HIBERNATE_MAX_DRAIN_PER_HOUR: f64 = 0.2;
+
+/// Gaps shorter than this are too brief for whole-percent capacity readings
+/// to tell hibernate from suspend, so they are assumed to be suspend.
+const HIBERNATE_MIN_SECS: i64 = 2 * 3600;
+
+/// How often today's process log is reread while the panel is shown; the
+/// daemon appends to it once per window.
+const PROCESS_RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepKind {
+    /// Suspend-to-RAM.
+    Suspend,
+    /// Suspend-to-disk, resumed within the same boot.
+    Hibernate,
+    /// Shutdown or reboot, detected from a changed boot ID.
+    PowerOff,
+    /// The daemon was not running, e.g. after a crash, within the same boot.
+    DaemonDown,
+}
+
+impl SleepKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SleepKind::Suspend => "Sleep",
+            SleepKind::Hibernate => "Hibernate",
+            SleepKind::PowerOff => "Power off",
+            SleepKind::DaemonDown => "Logger down",
+        }
+    }
+
+    /// Whether the system was actually asleep, so that the gap counts
+    /// towards sleep-drain statistics.
+    pub fn is_sleep(&self) -> bool {
+        matches!(self, SleepKind::Suspend | SleepKind::Hibernate)
+    }
+
+    /// Classifies the gap between two samples from their boot IDs and how
+    /// the capacity changed across it.
+    fn classify(before: &BatteryRecord, after: &BatteryRecord, duration_secs: i64) -> Self {
+        if let (Some(a), Some(b)) = (&before.boot_id, &after.boot_id)
+            && a != b
+        {
+            return SleepKind::PowerOff;
+        }
+
+        // While charging or full on AC the capacity says nothing about how
+        // much the machine itself drew.
+        if before.status != "Discharging" || duration_secs < HIBERNATE_MIN_SECS {
+            return SleepKind::Suspend;
+        }
+
+        let hours = duration_secs as f64 / 3600.0;
+        let drain_rate = (before.capacity - after.capacity) / hours;
+        if drain_rate <= HIBERNATE_MAX_DRAIN_PER_HOUR {
+            SleepKind::Hibernate
+        } else {
+            SleepKind::Suspend
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct SleepPeriod {
@@ -16,16 +84,89 @@ pub struct SleepPeriod {
     pub end_time: i64,
     pub duration_secs: i64,
     pub capacity_diff: f64,
+    pub kind: SleepKind,
 }
 
 pub struct ChartData {
     pub capacity_data: Vec<(f64, f64)>,
     pub power_data: Vec<(f64, f64)>,
+    /// Battery and CPU temperatures, empty unless they were logged. Both are
+    /// scaled so that `temperature_max` °C is at 100.
+    pub temperature_data: Vec<(f64, f64)>,
+    pub cpu_temperature_data: Vec<(f64, f64)>,
+    pub temperature_max: f64,
+    /// CPU package plus DRAM power from RAPL, empty unless it was logged.
+    pub soc_power_data: Vec<(f64, f64)>,
+    /// The selected overlay, scaled so that `overlay_max` is at 100.
+    pub overlay_data: Vec<(f64, f64)>,
+    pub overlay_max: f64,
+    /// Position of the point shown in the inspector.
+    pub inspect_x: Option<f64>,
     pub time_range: (f64, f64),
     pub sleep_markers: Vec<(f64, SleepPeriod)>,
+    /// Points where external power was connected (`true`) or removed.
+    pub adapter_markers: Vec<(f64, bool)>,
     pub x_labels: Vec<String>,
 }
 
+/// Wear history of one battery, with x in days since the first record.
+pub struct HealthSeries {
+    pub battery: String,
+    /// Full-charge capacity as a percentage of the design capacity, or of
+    /// `capacity_reference` for a battery that reports no design capacity.
+    pub health_data: Vec<(f64, f64)>,
+    /// Largest full-charge capacity logged and its unit, when `health_data`
+    /// is relative to it.
+    pub capacity_reference: Option<(f64, &'static str)>,
+    pub cycle_data: Vec<(f64, f64)>,
+}
+
+pub struct HealthChartData {
+    pub series: Vec<HealthSeries>,
+    pub day_range: (f64, f64),
+    pub max_cycles: f64,
+    pub x_labels: Vec<String>,
+}
+
+/// Context series the chart can draw on top of capacity and power.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overlay {
+    None,
+    Brightness,
+    CpuFreq,
+    Load,
+}
+
+impl Overlay {
+    pub fn next(self) -> Self {
+        match self {
+            Overlay::None => Overlay::Brightness,
+            Overlay::Brightness => Overlay::CpuFreq,
+            Overlay::CpuFreq => Overlay::Load,
+            Overlay::Load => Overlay::None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Overlay::None => "",
+            Overlay::Brightness => "Brightness",
+            Overlay::CpuFreq => "CPU freq",
+            Overlay::Load => "Load",
+        }
+    }
+
+    /// The record field the overlay plots, if any.
+    fn field(self) -> Option<fn(&BatteryRecord) -> Option<f64>> {
+        match self {
+            Overlay::None => None,
+            Overlay::Brightness => Some(|r| r.brightness),
+            Overlay::CpuFreq => Some(|r| r.cpu_freq_mhz),
+            Overlay::Load => Some(|r| r.load),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ViewMode {
     Recent30m,
@@ -87,31 +228,131 @@ impl ViewMode {
     }
 }
 
+/// Which battery series the chart shows on machines with several batteries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatterySelection {
+    Combined,
+    Single(String),
+}
+
 pub struct App {
+    pub config: Config,
+    pub all_records: Vec<BatteryRecord>,
     pub records: Vec<BatteryRecord>,
+    /// Start, suspend and resume events logged by the daemon.
+    pub events: Vec<LogEvent>,
+    pub battery_names: Vec<String>,
+    pub battery_selection: BatterySelection,
     pub current_date: NaiveDate,
     pub available_dates: Vec<NaiveDate>,
     pub today_record_count: usize,
+    /// Live feed from the daemon, when it is running.
+    pub subscription: Option<Subscription>,
     pub should_quit: bool,
     pub view_mode: ViewMode,
     pub show_service_warning: bool,
     pub show_about: bool,
+    /// Battery wear history, loaded when the health view is opened.
+    pub health: Vec<HealthRecord>,
+    pub show_health: bool,
+    /// Whether the chart plots the logged temperatures as a third series.
+    pub show_temperature: bool,
+    pub overlay: Overlay,
+    /// Time of the point shown in the inspector, if it is open.
+    pub inspect_time: Option<DateTime<Local>>,
+    /// Top energy consumers logged by the daemon, loaded while the panel
+    /// is shown.
+    pub processes: Vec<ProcessRecord>,
+    pub show_processes: bool,
+    processes_loaded_at: Option<Instant>,
 }
 
 impl App {
-    pub fn new(initial_date: NaiveDate, available_dates: Vec<NaiveDate>) -> Self {
-        let (records, today_record_count) = Self::load_records_for_date(initial_date);
+    pub fn new(config: Config, initial_date: NaiveDate, available_dates: Vec<NaiveDate>) -> Self {
+        let (log, today_record_count) = Self::load_records_for_date(&config, initial_date);
         let show_service_warning = !Self::is_logger_service_active();
 
-        App {
-            records,
+        let mut app = App {
+            config,
+            all_records: log.records,
+            records: Vec::new(),
+            events: log.events,
+            battery_names: Vec::new(),
+            battery_selection: BatterySelection::Combined,
             current_date: initial_date,
             available_dates,
             today_record_count,
+            subscription: None,
             should_quit: false,
             view_mode: ViewMode::Recent30m,
             show_service_warning,
             show_about: false,
+            health: Vec::new(),
+            show_health: false,
+            show_temperature: false,
+            overlay: Overlay::None,
+            inspect_time: None,
+            processes: Vec::new(),
+            show_processes: false,
+            processes_loaded_at: None,
+        };
+        app.rebuild_series();
+        app
+    }
+
+    /// Derives `records` from `all_records` for the selected battery.
+    fn rebuild_series(&mut self) {
+        self.battery_names = battery_names(&self.all_records);
+
+        if let BatterySelection::Single(name) = &self.battery_selection
+            && !self.battery_names.contains(name)
+        {
+            self.battery_selection = BatterySelection::Combined;
+        }
+
+        self.records = match &self.battery_selection {
+            BatterySelection::Combined => combine_batteries(&self.all_records, &self.events),
+            BatterySelection::Single(name) => self
+                .all_records
+                .iter()
+                .filter(|r| r.battery.as_ref() == Some(name))
+                .cloned()
+                .collect(),
+        };
+    }
+
+    /// Cycles between the combined series and each individual battery.
+    pub fn cycle_battery(&mut self) {
+        if self.battery_names.len() < 2 {
+            return;
+        }
+
+        let next_index = match &self.battery_selection {
+            BatterySelection::Combined => Some(0),
+            BatterySelection::Single(name) => self
+                .battery_names
+                .iter()
+                .position(|n| n == name)
+                .map(|i| i + 1)
+                .filter(|i| *i < self.battery_names.len()),
+        };
+
+        self.battery_selection = match next_index {
+            Some(i) => BatterySelection::Single(self.battery_names[i].clone()),
+            None => BatterySelection::Combined,
+        };
+        self.rebuild_series();
+    }
+
+    /// Returns the chart's battery label, or `None` with a single battery.
+    pub fn battery_label(&self) -> Option<&str> {
+        if self.battery_names.len() < 2 {
+            return None;
+        }
+
+        match &self.battery_selection {
+            BatterySelection::Combined => Some("All batteries"),
+            BatterySelection::Single(name) => Some(name.as_str()),
         }
     }
 
@@ -135,39 +376,154 @@ impl App {
         self.show_about = false;
     }
 
-    fn load_records_for_date(date: NaiveDate) -> (Vec<BatteryRecord>, usize) {
+    /// Switches between the power chart and the battery health history.
+    pub fn toggle_health(&mut self) {
+        self.show_health = !self.show_health;
+        if self.show_health {
+            self.health = read_health_log(&get_health_log_path(&self.config));
+        }
+    }
+
+    pub fn toggle_temperature(&mut self) {
+        self.show_temperature = !self.show_temperature;
+    }
+
+    pub fn cycle_overlay(&mut self) {
+        self.overlay = self.overlay.next();
+    }
+
+    /// Opens the point inspector on the latest sample, or closes it.
+    pub fn toggle_inspector(&mut self) {
+        self.inspect_time = match self.inspect_time {
+            Some(_) => None,
+            None => self.filtered_records().last().map(|r| r.time),
+        };
+    }
+
+    /// Moves the inspector `delta` samples later (or earlier when negative).
+    pub fn move_inspector(&mut self, delta: isize) {
+        let filtered = self.filtered_records();
+        let Some(index) = self.inspected_index(&filtered) else {
+            return;
+        };
+        let index = index.saturating_add_signed(delta).min(filtered.len() - 1);
+        self.inspect_time = Some(filtered[index].time);
+    }
+
+    /// Index of the inspected sample in `filtered`: the first at or after
+    /// the inspected time, which may have scrolled out of the view.
+    fn inspected_index(&self, filtered: &[&BatteryRecord]) -> Option<usize> {
+        let time = self.inspect_time?;
+        if filtered.is_empty() {
+            return None;
+        }
+        Some(
+            filtered
+                .iter()
+                .position(|r| r.time >= time)
+                .unwrap_or(filtered.len() - 1),
+        )
+    }
+
+    pub fn inspected_record(&self) -> Option<&BatteryRecord> {
+        let filtered = self.filtered_records();
+        self.inspected_index(&filtered).map(|i| filtered[i])
+    }
+
+    /// Shows or hides the top consumers panel.
+    pub fn toggle_processes(&mut self) {
+        self.show_processes = !self.show_processes;
+        if self.show_processes {
+            self.load_processes();
+        }
+    }
+
+    /// Loads the process log of the shown date, plus the previous day's for
+    /// today, whose recent views may reach back past midnight.
+    fn load_processes(&mut self) {
+        let mut dates = vec![self.current_date];
+        if self.is_today()
+            && let Some(yesterday) = self.current_date.pred_opt()
+        {
+            dates.insert(0, yesterday);
+        }
+        self.processes = dates
+            .into_iter()
+            .flat_map(|date| read_process_log(&get_process_log_path(&self.config, date)))
+            .collect();
+        self.processes_loaded_at = Some(Instant::now());
+    }
+
+    /// Sums the energy logged for each command in the windows ending within
+    /// the viewed time range and returns `(command, joules, cpu seconds)`,
+    /// highest energy first.
+    pub fn top_consumers(&self) -> Vec<(String, f64, f64)> {
+        let filtered = self.filtered_records();
+        let (Some(first), Some(last)) = (filtered.first(), filtered.last()) else {
+            return vec![];
+        };
+        let (start, end) = (first.time, last.time);
+
+        let mut totals: Vec<(String, f64, f64)> = Vec::new();
+        for record in &self.processes {
+            if !record
+                .parsed_time()
+                .is_some_and(|time| time >= start && time <= end)
+            {
+                continue;
+            }
+            match totals
+                .iter_mut()
+                .find(|(command, _, _)| *command == record.command)
+            {
+                Some((_, energy, cpu)) => {
+                    *energy += record.energy_joules;
+                    *cpu += record.cpu_secs;
+                }
+                None => totals.push((
+                    record.command.clone(),
+                    record.energy_joules,
+                    record.cpu_secs,
+                )),
+            }
+        }
+        totals.sort_by(|a, b| b.1.total_cmp(&a.1));
+        totals
+    }
+
+    fn load_records_for_date(config: &Config, date: NaiveDate) -> (LogData, usize) {
         let today = Local::now().date_naive();
-        let mut records = Vec::new();
+        let mut log = LogData::default();
         let mut today_count = 0;
 
         if date == today {
             if let Some(yesterday) = date.pred_opt() {
-                let yesterday_path = get_archive_path_for_date(yesterday);
-                if yesterday_path.exists() {
-                    if let Ok(yesterday_records) = parse_csv(&yesterday_path) {
-                        records.extend(yesterday_records);
-                    }
+                let yesterday_path = get_archive_path_for_date(config, yesterday);
+                if yesterday_path.exists()
+                    && let Ok(yesterday_log) = parse_csv(&yesterday_path)
+                {
+                    log.extend(yesterday_log);
                 }
             }
 
-            let today_archive_path = get_archive_path_for_date(date);
-            if today_archive_path.exists() {
-                if let Ok(today_archive_records) = parse_csv(&today_archive_path) {
-                    records.extend(today_archive_records);
-                }
+            let today_archive_path = get_archive_path_for_date(config, date);
+            if today_archive_path.exists()
+                && let Ok(today_archive_log) = parse_csv(&today_archive_path)
+            {
+                log.extend(today_archive_log);
             }
 
-            let today_path = get_csv_path_for_date(date);
-            if let Ok(today_records) = parse_csv(&today_path) {
-                today_count = today_records.len();
-                records.extend(today_records);
+            let today_path = get_csv_path_for_date(config, date);
+            if let Ok(today_log) = parse_csv(&today_path) {
+                today_count = today_log.rows;
+                log.extend(today_log);
             }
         } else {
-            let csv_path = get_csv_path_for_date(date);
-            records = parse_csv(&csv_path).unwrap_or_default();
+            let csv_path = get_csv_path_for_date(config, date);
+            log = parse_csv(&csv_path).unwrap_or_default();
         }
 
-        (records, today_count)
+        (log, today_count)
     }
 
     pub fn toggle_view_mode(&mut self) {
@@ -198,28 +554,103 @@ impl App {
     }
 
     fn load_date_data(&mut self) {
-        let (records, today_count) = Self::load_records_for_date(self.current_date);
-        self.records = records;
+        let (log, today_count) = Self::load_records_for_date(&self.config, self.current_date);
+        self.all_records = log.records;
+        self.events = log.events;
         self.today_record_count = today_count;
+        self.rebuild_series();
+        if self.show_processes {
+            self.load_processes();
+        }
     }
 
+    /// Picks up new rows for today: streamed from the daemon while its socket
+    /// is reachable, otherwise by polling the live log.
     pub fn refresh_data(&mut self) {
         if !self.is_today() {
             return;
         }
 
-        self.available_dates = list_available_dates();
+        self.available_dates = list_available_dates(&self.config);
+
+        if self.show_processes
+            && self
+                .processes_loaded_at
+                .is_none_or(|at| at.elapsed() >= PROCESS_RELOAD_INTERVAL)
+        {
+            self.load_processes();
+        }
 
-        let csv_path = get_csv_path_for_date(self.current_date);
-        if let Ok(new_records) = parse_csv_from_line(&csv_path, self.today_record_count) {
-            let count = new_records.len();
-            if count > 0 {
-                self.records.extend(new_records);
-                self.today_record_count += count;
+        if self.subscription.is_none()
+            && let Ok(subscription) = Subscription::connect(&get_socket_path())
+        {
+            // Rows flushed before subscribing are only on disk.
+            self.subscription = Some(subscription);
+            self.load_date_data();
+        }
+
+        if let Some(subscription) = &self.subscription {
+            let mut live = LogData::default();
+            let connected = subscription.poll(&mut live);
+            if self.append_live(live) {
+                self.rebuild_series();
+            }
+            if !connected {
+                // Whatever the daemon wrote before exiting is on disk now.
+                self.subscription = None;
+                self.load_date_data();
             }
+            return;
+        }
+
+        let csv_path = get_csv_path_for_date(&self.config, self.current_date);
+        let Ok(new_log) = parse_csv_from_line(&csv_path, self.today_record_count) else {
+            return;
+        };
+
+        if new_log.shrunk {
+            // The rows already shown now live in the archive.
+            self.load_date_data();
+        } else if new_log.rows > 0 {
+            self.all_records.extend(new_log.records);
+            self.events.extend(new_log.events);
+            self.today_record_count += new_log.rows;
+            self.rebuild_series();
         }
     }
 
+    /// Appends streamed rows, skipping ones already loaded from disk, and
+    /// returns whether anything was added.
+    fn append_live(&mut self, live: LogData) -> bool {
+        let mut changed = false;
+
+        for record in live.records {
+            let loaded = self
+                .all_records
+                .iter()
+                .rev()
+                .take_while(|r| r.time >= record.time)
+                .any(|r| r.time == record.time && r.battery == record.battery);
+            if !loaded {
+                self.all_records.push(record);
+                changed = true;
+            }
+        }
+
+        for event in live.events {
+            let loaded = self
+                .events
+                .iter()
+                .any(|e| e.time == event.time && e.kind == event.kind);
+            if !loaded {
+                self.events.push(event);
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
     fn filtered_records_for_mode(&self, mode: ViewMode) -> Vec<&BatteryRecord> {
         if self.records.is_empty() {
             return vec![];
@@ -235,7 +666,7 @@ impl App {
                     .collect()
             }
             None => {
-                let max_points = 500;
+                let max_points = self.config.tui.max_chart_points.max(1);
                 if self.records.len() <= max_points {
                     self.records.iter().collect()
                 } else {
@@ -269,11 +700,11 @@ impl App {
                 let last_time = filtered.last().unwrap().time.timestamp();
                 let data_duration = last_time - first_time;
 
-                if data_duration < current_mode.min_data_secs() {
-                    if let Some(expanded) = current_mode.expand() {
-                        current_mode = expanded;
-                        continue;
-                    }
+                if data_duration < current_mode.min_data_secs()
+                    && let Some(expanded) = current_mode.expand()
+                {
+                    current_mode = expanded;
+                    continue;
                 }
             }
             break;
@@ -295,6 +726,10 @@ impl App {
         self.filtered_records_for_mode(self.effective_view_mode())
     }
 
+    pub fn latest_record(&self) -> Option<&BatteryRecord> {
+        self.records.last()
+    }
+
     pub fn latest_capacity(&self) -> Option<f64> {
         self.records.last().map(|r| r.capacity)
     }
@@ -303,6 +738,29 @@ impl App {
         self.records.last().map(|r| r.power)
     }
 
+    pub fn latest_soc_power(&self) -> Option<f64> {
+        self.records.last().and_then(BatteryRecord::soc_power)
+    }
+
+    /// Returns the power source of the latest sample when it was estimated
+    /// rather than read from `power_now`.
+    pub fn latest_power_estimate_source(&self) -> Option<&str> {
+        self.records
+            .last()
+            .and_then(|r| r.power_source.as_deref())
+            .filter(|source| *source != "power_now")
+    }
+
+    /// Describes the connected adapter of the latest sample, e.g. `USB-PD 20V`.
+    pub fn latest_adapter(&self) -> Option<String> {
+        let record = self.records.last()?;
+        let adapter = record.adapter.as_deref()?;
+        Some(match record.adapter_max_voltage {
+            Some(volts) if adapter != "Offline" => format!("{} {:.0}V", adapter, volts),
+            _ => adapter.to_string(),
+        })
+    }
+
     pub fn latest_status(&self) -> Option<&str> {
         self.records.last().map(|r| r.status.as_str())
     }
@@ -313,33 +771,56 @@ impl App {
             return (0.0, 20.0);
         }
 
-        let min = filtered
-            .iter()
-            .map(|r| r.power)
-            .fold(f64::INFINITY, f64::min);
-        let max = filtered
-            .iter()
-            .map(|r| r.power)
-            .fold(f64::NEG_INFINITY, f64::max);
+        // SoC power shares the power scale, and may be all there is on AC.
+        let powers = || {
+            filtered
+                .iter()
+                .flat_map(|r| std::iter::once(r.power).chain(r.soc_power()))
+        };
+        let min = powers().fold(f64::INFINITY, f64::min);
+        let max = powers().fold(f64::NEG_INFINITY, f64::max);
 
         let padding = (max - min) * 0.1;
         ((min - padding).max(0.0), max + padding)
     }
 
+    /// Returns the gaps in the loaded data, oldest first.
+    ///
+    /// Resume events logged by the daemon give exact suspend durations and are
+    /// used whenever present. Gaps without one, e.g. in logs written by older
+    /// versions, fall back to the gap length and drain-rate heuristic. Gaps
+    /// across a boot ID change are always reported as power-off periods, and
+    /// gaps across a daemon start as logger-down periods once they reach
+    /// the sleep threshold.
     pub fn detect_sleep_periods(&self) -> Vec<SleepPeriod> {
         if self.records.len() < 2 {
             return vec![];
         }
 
-        let mut sleep_periods = Vec::new();
+        let mut sleep_periods = self.sleep_periods_from_events();
 
         for i in 1..self.records.len() {
             let prev = &self.records[i - 1];
             let curr = &self.records[i];
 
+            let event_between = |kind: EventKind| {
+                self.events
+                    .iter()
+                    .any(|e| e.kind == kind && e.time > prev.time && e.time <= curr.time)
+            };
+            if event_between(EventKind::Resume) {
+                continue;
+            }
+
             let time_diff = curr.time.timestamp() - prev.time.timestamp();
+            let mut kind = SleepKind::classify(prev, curr, time_diff);
+            if kind != SleepKind::PowerOff && event_between(EventKind::Start) {
+                kind = SleepKind::DaemonDown;
+            }
 
-            if time_diff < SLEEP_THRESHOLD_SECS {
+            // A reboot is worth marking however quick it was, but a daemon
+            // restarted within the threshold is not.
+            if kind != SleepKind::PowerOff && time_diff < self.config.tui.sleep_threshold_secs {
                 continue;
             }
 
@@ -351,7 +832,7 @@ impl App {
                 0.0
             };
 
-            if drain_rate > MAX_SLEEP_DRAIN_RATE_PER_HOUR {
+            if kind.is_sleep() && drain_rate > self.config.tui.max_sleep_drain_rate_per_hour {
                 continue;
             }
 
@@ -360,14 +841,52 @@ impl App {
                 end_time: curr.time.timestamp(),
                 duration_secs: time_diff,
                 capacity_diff: curr.capacity - prev.capacity,
+                kind,
             });
         }
 
+        sleep_periods.sort_by_key(|sp| sp.start_time);
         sleep_periods
     }
 
+    /// Builds sleep periods from the daemon's resume events, which carry the
+    /// time measured as suspended by the kernel.
+    fn sleep_periods_from_events(&self) -> Vec<SleepPeriod> {
+        self.events
+            .iter()
+            .filter(|e| e.kind == EventKind::Resume)
+            .filter_map(|e| {
+                let duration_secs = e.suspended_secs?;
+                let end_time = e.time.timestamp();
+                let start_time = end_time - duration_secs;
+
+                let after_idx = self
+                    .records
+                    .partition_point(|r| r.time.timestamp() < end_time);
+                let before_idx = self
+                    .records
+                    .partition_point(|r| r.time.timestamp() <= start_time);
+                let before = self.records.get(before_idx.checked_sub(1)?)?;
+                let after = self.records.get(after_idx)?;
+
+                Some(SleepPeriod {
+                    start_time,
+                    end_time,
+                    duration_secs,
+                    capacity_diff: after.capacity - before.capacity,
+                    kind: SleepKind::classify(before, after, duration_secs),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the last actual sleep, skipping reboots and logger downtime
+    /// whose capacity change says nothing about sleep drain.
     pub fn last_sleep_period(&self) -> Option<SleepPeriod> {
-        self.detect_sleep_periods().into_iter().last()
+        self.detect_sleep_periods()
+            .into_iter()
+            .rev()
+            .find(|sp| sp.kind.is_sleep())
     }
 
     fn total_sleep_before(timestamp: i64, base_time: i64, sleep_periods: &[SleepPeriod]) -> i64 {
@@ -411,10 +930,9 @@ impl App {
             return None;
         }
 
-        let sleep_periods = self.detect_sleep_periods();
         let last_record = self.records.last().unwrap();
 
-        let start_time = if let Some(last_sleep) = sleep_periods.last() {
+        let start_time = if let Some(last_sleep) = self.last_sleep_period() {
             last_sleep.end_time
         } else {
             self.records.first().unwrap().time.timestamp()
@@ -423,7 +941,7 @@ impl App {
         let start_record = self
             .records
             .iter()
-            .find(|r| r.time.timestamp() == start_time);
+            .find(|r| r.time.timestamp() >= start_time);
 
         if let Some(start) = start_record {
             let duration = last_record.time.timestamp() - start.time.timestamp();
@@ -447,8 +965,16 @@ impl App {
             return ChartData {
                 capacity_data: vec![],
                 power_data: vec![],
+                temperature_data: vec![],
+                cpu_temperature_data: vec![],
+                temperature_max: 0.0,
+                soc_power_data: vec![],
+                overlay_data: vec![],
+                overlay_max: 0.0,
+                inspect_x: None,
                 time_range: (0.0, 60.0),
                 sleep_markers: vec![],
+                adapter_markers: vec![],
                 x_labels: vec!["".to_string(), "".to_string(), "".to_string()],
             };
         }
@@ -479,6 +1005,54 @@ impl App {
             })
             .collect();
 
+        let series = |field: fn(&BatteryRecord) -> Option<f64>| -> Vec<(f64, f64)> {
+            filtered
+                .iter()
+                .filter_map(|r| {
+                    let x = Self::to_compressed_x(r.time.timestamp(), base_time, &sleep_in_view);
+                    field(r).map(|value| (x, value))
+                })
+                .collect()
+        };
+        let temperature_data = series(|r| r.temperature);
+        let cpu_temperature_data = series(|r| r.cpu_temperature);
+        let temperature_max = temperature_data
+            .iter()
+            .chain(&cpu_temperature_data)
+            .map(|(_, v)| *v)
+            .fold(0.0, f64::max);
+        let scale_temperature = |data: Vec<(f64, f64)>| -> Vec<(f64, f64)> {
+            if temperature_max > 0.0 {
+                data.into_iter()
+                    .map(|(x, v)| (x, v / temperature_max * 100.0))
+                    .collect()
+            } else {
+                vec![]
+            }
+        };
+        let temperature_data = scale_temperature(temperature_data);
+        let cpu_temperature_data = scale_temperature(cpu_temperature_data);
+        let soc_power_data = series(BatteryRecord::soc_power);
+
+        let overlay_data = self.overlay.field().map(series).unwrap_or_default();
+        let overlay_max = match self.overlay {
+            // Already a percentage.
+            Overlay::Brightness => 100.0,
+            _ => overlay_data.iter().map(|(_, v)| *v).fold(0.0, f64::max),
+        };
+        let overlay_data = if overlay_max > 0.0 {
+            overlay_data
+                .into_iter()
+                .map(|(x, v)| (x, v / overlay_max * 100.0))
+                .collect()
+        } else {
+            vec![]
+        };
+
+        let inspect_x = self.inspected_index(&filtered).map(|i| {
+            Self::to_compressed_x(filtered[i].time.timestamp(), base_time, &sleep_in_view)
+        });
+
         let total_sleep: i64 = sleep_in_view
             .iter()
             .map(|sp| {
@@ -499,21 +1073,283 @@ impl App {
             })
             .collect();
 
+        let adapter_markers: Vec<(f64, bool)> = filtered
+            .windows(2)
+            .filter_map(|pair| {
+                let before = pair[0].adapter_online()?;
+                let after = pair[1].adapter_online()?;
+                (before != after).then(|| {
+                    let x =
+                        Self::to_compressed_x(pair[1].time.timestamp(), base_time, &sleep_in_view);
+                    (x, after)
+                })
+            })
+            .collect();
+
         let start_label = filtered.first().unwrap().time.format("%H:%M").to_string();
         let end_label = filtered.last().unwrap().time.format("%H:%M").to_string();
         let mid_compressed = compressed_duration / 2.0;
         let mid_label =
             Self::find_record_at_compressed_x(mid_compressed, &filtered, base_time, &sleep_in_view)
                 .map(|r| r.time.format("%H:%M").to_string())
-                .unwrap_or_else(|| "".to_string());
+                .unwrap_or_default();
         let x_labels = vec![start_label, mid_label, end_label];
 
         ChartData {
             capacity_data,
             power_data,
+            temperature_data,
+            cpu_temperature_data,
+            temperature_max,
+            soc_power_data,
+            overlay_data,
+            overlay_max,
+            inspect_x,
             time_range,
             sleep_markers,
+            adapter_markers,
             x_labels,
         }
     }
+
+    /// Health history of the selected battery, or of every battery when
+    /// the combined series is shown.
+    pub fn health_chart_data(&self) -> HealthChartData {
+        let records: Vec<&HealthRecord> = self
+            .health
+            .iter()
+            .filter(|r| match &self.battery_selection {
+                BatterySelection::Combined => true,
+                BatterySelection::Single(name) => &r.battery == name,
+            })
+            .collect();
+
+        let first_date = records.iter().map(|r| r.date).min();
+        let last_date = records.iter().map(|r| r.date).max();
+        let (Some(first_date), Some(last_date)) = (first_date, last_date) else {
+            return HealthChartData {
+                series: vec![],
+                day_range: (0.0, 1.0),
+                max_cycles: 0.0,
+                x_labels: vec!["".to_string(), "".to_string(), "".to_string()],
+            };
+        };
+        let day = |date: NaiveDate| (date - first_date).num_days() as f64;
+
+        let mut by_battery: BTreeMap<&str, Vec<&HealthRecord>> = BTreeMap::new();
+        for record in &records {
+            by_battery.entry(&record.battery).or_default().push(record);
+        }
+
+        let mut series: Vec<HealthSeries> = Vec::new();
+        for (battery, records) in by_battery {
+            let mut health_data: Vec<(f64, f64)> = records
+                .iter()
+                .filter_map(|r| Some((day(r.date), r.health_percent()?)))
+                .collect();
+            // Without a design capacity, plot the full-charge capacity
+            // against the largest one logged instead.
+            let mut capacity_reference = None;
+            if health_data.is_empty()
+                && let Some(reference) = records
+                    .iter()
+                    .filter_map(|r| r.full_capacity())
+                    .max_by(|a, b| a.0.total_cmp(&b.0))
+                    .filter(|(full, _)| *full > 0.0)
+            {
+                health_data = records
+                    .iter()
+                    .filter_map(|r| Some((day(r.date), r.full_capacity()?.0 / reference.0 * 100.0)))
+                    .collect();
+                capacity_reference = Some(reference);
+            }
+
+            series.push(HealthSeries {
+                battery: battery.to_string(),
+                health_data,
+                capacity_reference,
+                cycle_data: records
+                    .iter()
+                    .filter_map(|r| Some((day(r.date), r.cycle_count? as f64)))
+                    .collect(),
+            });
+        }
+
+        let max_cycles = series
+            .iter()
+            .flat_map(|s| s.cycle_data.iter().map(|(_, cycles)| *cycles))
+            .fold(0.0, f64::max);
+        let mid_date = first_date + (last_date - first_date) / 2;
+
+        HealthChartData {
+            series,
+            day_range: (0.0, day(last_date).max(1.0)),
+            max_cycles,
+            x_labels: [first_date, mid_date, last_date]
+                .iter()
+                .map(|date| date.format("%Y-%m-%d").to_string())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, TimeZone};
+
+    use super::*;
+
+    /// `secs` seconds into a fixed day.
+    fn at(secs: i64) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 1, 10, 0, 0, 0).unwrap() + TimeDelta::seconds(secs)
+    }
+
+    fn record(secs: i64, capacity: f64, boot_id: &str) -> BatteryRecord {
+        BatteryRecord {
+            time: at(secs),
+            status: "Discharging".to_string(),
+            capacity,
+            boot_id: Some(boot_id.to_string()),
+            ..BatteryRecord::default()
+        }
+    }
+
+    fn event(secs: i64, kind: EventKind, suspended_secs: Option<i64>) -> LogEvent {
+        LogEvent {
+            time: at(secs),
+            kind,
+            suspended_secs,
+        }
+    }
+
+    fn app(records: Vec<BatteryRecord>, events: Vec<LogEvent>) -> App {
+        App {
+            config: Config::default(),
+            all_records: records.clone(),
+            records,
+            events,
+            battery_names: Vec::new(),
+            battery_selection: BatterySelection::Combined,
+            current_date: at(0).date_naive(),
+            available_dates: Vec::new(),
+            today_record_count: 0,
+            subscription: None,
+            should_quit: false,
+            view_mode: ViewMode::Full,
+            show_service_warning: false,
+            show_about: false,
+            health: Vec::new(),
+            show_health: false,
+            show_temperature: false,
+            overlay: Overlay::None,
+            inspect_time: None,
+            processes: Vec::new(),
+            show_processes: false,
+            processes_loaded_at: None,
+        }
+    }
+
+    #[test]
+    fn daemon_restart_is_marked_once_it_reaches_the_threshold() {
+        let app = app(
+            vec![record(0, 80.0, "a"), record(3600, 79.0, "a")],
+            vec![event(3590, EventKind::Start, None)],
+        );
+
+        let periods = app.detect_sleep_periods();
+        assert_eq!(periods.len(), 1);
+        assert_eq!(periods[0].kind, SleepKind::DaemonDown);
+        assert_eq!(periods[0].duration_secs, 3600);
+    }
+
+    #[test]
+    fn quick_daemon_restart_is_not_marked() {
+        let app = app(
+            vec![record(0, 80.0, "a"), record(8, 80.0, "a")],
+            vec![event(6, EventKind::Start, None)],
+        );
+
+        assert!(app.detect_sleep_periods().is_empty());
+    }
+
+    #[test]
+    fn awake_stats_start_after_the_last_sleep_only() {
+        let app = app(
+            vec![
+                record(0, 80.0, "a"),
+                record(3600, 79.0, "a"),
+                record(4000, 78.0, "a"),
+                record(7600, 72.0, "a"),
+                record(8000, 70.0, "a"),
+            ],
+            vec![
+                event(3600, EventKind::Resume, Some(3590)),
+                event(7590, EventKind::Start, None),
+            ],
+        );
+
+        let (duration, capacity_diff, _) = app.get_current_awake_stats().unwrap();
+        assert_eq!(duration, 8000 - 3600);
+        assert_eq!(capacity_diff, 70.0 - 79.0);
+    }
+
+    #[test]
+    fn boot_id_change_is_power_off_however_short() {
+        let app = app(vec![record(0, 80.0, "a"), record(60, 80.0, "b")], vec![]);
+
+        let periods = app.detect_sleep_periods();
+        assert_eq!(periods.len(), 1);
+        assert_eq!(periods[0].kind, SleepKind::PowerOff);
+    }
+
+    #[test]
+    fn long_gap_with_little_drain_is_hibernate() {
+        let hours = 3 * 3600;
+        let before = record(0, 80.0, "a");
+        let slow = record(hours, 80.0 - 2.0 * HIBERNATE_MAX_DRAIN_PER_HOUR, "a");
+        let fast = record(hours, 79.0, "a");
+
+        assert_eq!(
+            SleepKind::classify(&before, &slow, hours),
+            SleepKind::Hibernate
+        );
+        assert_eq!(
+            SleepKind::classify(&before, &fast, hours),
+            SleepKind::Suspend
+        );
+    }
+
+    #[test]
+    fn short_or_charging_gap_is_suspend() {
+        let before = record(0, 80.0, "a");
+        let after = record(HIBERNATE_MIN_SECS - 1, 80.0, "a");
+        assert_eq!(
+            SleepKind::classify(&before, &after, HIBERNATE_MIN_SECS - 1),
+            SleepKind::Suspend
+        );
+
+        let charging = BatteryRecord {
+            status: "Charging".to_string(),
+            ..record(0, 80.0, "a")
+        };
+        let after = record(3 * 3600, 80.0, "a");
+        assert_eq!(
+            SleepKind::classify(&charging, &after, 3 * 3600),
+            SleepKind::Suspend
+        );
+    }
+
+    #[test]
+    fn resume_event_gives_the_exact_suspend() {
+        let app = app(
+            vec![record(0, 80.0, "a"), record(4000, 79.0, "a")],
+            vec![event(4000, EventKind::Resume, Some(1000))],
+        );
+
+        let periods = app.detect_sleep_periods();
+        assert_eq!(periods.len(), 1);
+        assert_eq!(periods[0].start_time, at(3000).timestamp());
+        assert_eq!(periods[0].end_time, at(4000).timestamp());
+        assert_eq!(periods[0].kind, SleepKind::Suspend);
+    }
 }
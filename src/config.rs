@@ -0,0 +1,162 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::{get_data_dir, get_today_log_path};
+use crate::logging::LogLevel;
+use crate::sampling::{HEARTBEAT_SECS, SamplingMode};
+
+/// Settings shared by the daemon and the TUI, loaded from
+/// `$XDG_CONFIG_HOME/watt-monitor/config.toml`.
+///
+/// Every field is optional in the file; missing ones fall back to the
+/// built-in defaults and CLI flags override the result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory holding the daily `YYYY-MM-DD.csv` archives.
+    pub data_dir: PathBuf,
+    /// CSV the daemon appends to during the current day.
+    pub live_log: PathBuf,
+    pub daemon: DaemonConfig,
+    pub tui: TuiConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    pub log_interval_secs: u64,
+    pub sampling: SamplingMode,
+    /// Shortest interval adaptive sampling may use during power spikes.
+    pub min_interval_secs: u64,
+    /// Longest interval adaptive sampling may back off to, capped at one
+    /// minute so that a heartbeat is always written.
+    pub max_interval_secs: u64,
+    /// Log only the power supply with this name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery: Option<String>,
+    /// Mount point of sysfs, `/sys` unless overridden for tests or containers.
+    pub sysfs_root: PathBuf,
+    /// How often the live log is moved into the persistent archives, which
+    /// bounds what a crash or reboot can lose. 0 only archives at midnight
+    /// and on shutdown.
+    pub checkpoint_interval_secs: u64,
+    /// How long samples are buffered in memory before being appended to the
+    /// live log. 0 writes every sample immediately.
+    pub flush_interval_secs: u64,
+    /// Least severe messages the daemon writes to stderr.
+    pub log_level: LogLevel,
+    /// Log the temperature of each battery that reports one.
+    pub battery_temperature: bool,
+    /// Temperature sensor logged alongside each sample: a hwmon chip with an
+    /// optional channel label (`coretemp/Package id 0`), a thermal zone type
+    /// (`x86_pkg_temp`) or a path to a `temp*_input` file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_sensor: Option<String>,
+    /// Log CPU package, core, uncore and DRAM power from the RAPL energy
+    /// counters, which usually requires running as root.
+    pub rapl: bool,
+    /// Attribute the measured power to processes by their CPU time and log
+    /// the top consumers.
+    pub processes: bool,
+    /// Number of processes logged per window.
+    pub top_processes: usize,
+    /// Length of the windows the top consumers are logged for.
+    pub process_interval_secs: u64,
+    /// Mount point of procfs, `/proc` unless overridden for tests.
+    pub proc_root: PathBuf,
+    /// Log display brightness, average CPU frequency and load average with
+    /// each sample.
+    pub system_state: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TuiConfig {
+    /// Gaps between samples at least this long are treated as sleep.
+    pub sleep_threshold_secs: i64,
+    /// Gaps draining faster than this are not counted as sleep.
+    pub max_sleep_drain_rate_per_hour: f64,
+    /// Number of points the Full view is down-sampled to.
+    pub max_chart_points: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            data_dir: get_data_dir(),
+            live_log: get_today_log_path(),
+            daemon: DaemonConfig::default(),
+            tui: TuiConfig::default(),
+        }
+    }
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        DaemonConfig {
+            log_interval_secs: 4,
+            sampling: SamplingMode::Fixed,
+            min_interval_secs: 1,
+            max_interval_secs: HEARTBEAT_SECS,
+            battery: None,
+            sysfs_root: PathBuf::from("/sys"),
+            checkpoint_interval_secs: 15 * 60,
+            flush_interval_secs: 5 * 60,
+            log_level: LogLevel::Info,
+            battery_temperature: false,
+            cpu_sensor: None,
+            rapl: false,
+            processes: false,
+            top_processes: 5,
+            process_interval_secs: 60,
+            proc_root: PathBuf::from("/proc"),
+            system_state: false,
+        }
+    }
+}
+
+impl Default for TuiConfig {
+    fn default() -> Self {
+        TuiConfig {
+            sleep_threshold_secs: 10 * 60,
+            max_sleep_drain_rate_per_hour: 5.0,
+            max_chart_points: 500,
+        }
+    }
+}
+
+pub fn get_config_path() -> PathBuf {
+    let base = std::env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            let home = std::env::var("HOME").expect("HOME not set");
+            PathBuf::from(home).join(".config")
+        });
+    base.join("watt-monitor/config.toml")
+}
+
+impl Config {
+    /// Loads the config file, returning the defaults when it does not exist.
+    pub fn load() -> io::Result<Self> {
+        let path = get_config_path();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e),
+        };
+
+        toml::from_str(&content).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid config file {:?}: {}", path, e),
+            )
+        })
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("config is always serializable")
+    }
+}
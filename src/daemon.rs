@@ -1,38 +1,499 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs::{self, File, OpenOptions};
-use std::io::{self, Write};
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use chrono::{Local, NaiveDate};
-use signal_hook::consts::{SIGINT, SIGTERM};
-use signal_hook::flag;
+use chrono::{DateTime, Local, NaiveDate, TimeDelta};
+use serde::Serialize;
+use signal_hook::consts::{SIGINT, SIGTERM, SIGUSR1};
+use signal_hook::iterator::Signals;
 
-use crate::data::{get_data_dir, get_today_log_path};
+use crate::config::Config;
+use crate::data::{
+    HealthRecord, ProcessRecord, create_runtime_dir, csv_row_to_json, current_uid,
+    get_archive_path_for_date, get_health_log_path, get_process_log_path, get_runtime_dir,
+    read_health_log,
+};
+use crate::ipc::{self, Request, get_socket_path};
+use crate::logging::{RateLimit, debug, error, info, warn};
+use crate::notify::Notifier;
+use crate::processes::{ProcessCollector, ProcessUsage};
+use crate::rapl::{RaplCollector, RaplPower};
+use crate::sampling::{
+    IntervalPolicy, SampleSummary, SamplingMode, Scheduler, Shutdown, SuspendDetector,
+};
 
-const LOG_INTERVAL_SECS: u64 = 4;
-const CSV_HEADER: &str = "Time,Status,Capacity(%),Power(W)";
+const CSV_HEADER: &str = "Time,Status,Capacity(%),Power(W),PowerSource,\
+Voltage(V),Current(A),EnergyNow(Wh),EnergyFull(Wh),ChargeNow(Ah),ChargeFull(Ah),Battery,Adapter,AdapterMax(V),Missed,Event,Suspended(s),BootId,Temp(C),CpuTemp(C),\
+RaplPackage(W),RaplCore(W),RaplUncore(W),RaplDram(W),Brightness(%),CpuFreq(MHz),Load1";
+
+/// Where the logged power figure came from, in order of preference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PowerSource {
+    PowerNow,
+    CurrentVoltage,
+    EnergyDelta,
+    ChargeDelta,
+    Unavailable,
+}
+
+impl PowerSource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PowerSource::PowerNow => "power_now",
+            PowerSource::CurrentVoltage => "current_voltage",
+            PowerSource::EnergyDelta => "energy_delta",
+            PowerSource::ChargeDelta => "charge_delta",
+            PowerSource::Unavailable => "none",
+        }
+    }
+}
 
 struct BatteryInfo {
     timestamp: String,
+    battery: String,
     status: String,
     capacity: u8,
     power_watts: f64,
+    power_source: PowerSource,
+    voltage: Option<f64>,
+    current: Option<f64>,
+    energy_now: Option<f64>,
+    energy_full: Option<f64>,
+    charge_now: Option<f64>,
+    charge_full: Option<f64>,
+    temperature: Option<f64>,
+}
+
+/// Last counter reading used to derive power from `energy_now`/`charge_now`.
+struct CounterSample {
+    at: Instant,
+    source: PowerSource,
+    value: f64,
+}
+
+/// Derives power from successive `energy_now` (µWh) or `charge_now` (µAh)
+/// readings for batteries that expose neither `power_now` nor `current_now`.
+///
+/// Firmware usually refreshes these counters every 15-60 seconds, so the
+/// previous estimate is kept until the counter moves again.
+#[derive(Default)]
+struct PowerEstimator {
+    anchor: Option<CounterSample>,
+    last_watts: Option<f64>,
+}
+
+impl PowerEstimator {
+    /// Counters that have not moved for this long mean the battery is idle.
+    const STALE_AFTER: Duration = Duration::from_secs(5 * 60);
+
+    /// `value` is in Wh for `EnergyDelta` and in Ah for `ChargeDelta`;
+    /// `volts` converts the latter to watts.
+    fn update(&mut self, now: Instant, source: PowerSource, value: f64, volts: f64) -> Option<f64> {
+        let anchor = match &self.anchor {
+            Some(anchor) if anchor.source == source => anchor,
+            _ => {
+                self.anchor = Some(CounterSample {
+                    at: now,
+                    source,
+                    value,
+                });
+                self.last_watts = None;
+                return None;
+            }
+        };
+
+        let elapsed = now.duration_since(anchor.at);
+        if value == anchor.value {
+            if elapsed >= Self::STALE_AFTER {
+                self.last_watts = Some(0.0);
+            }
+            return self.last_watts;
+        }
+
+        let hours = elapsed.as_secs_f64() / 3600.0;
+        let delta = (value - anchor.value).abs();
+        let watts = match source {
+            PowerSource::ChargeDelta => delta * volts / hours,
+            _ => delta / hours,
+        };
+
+        self.anchor = Some(CounterSample {
+            at: now,
+            source,
+            value,
+        });
+        self.last_watts = Some(watts);
+        self.last_watts
+    }
+
+    /// Forgets the anchor after a suspend. `Instant` stands still while the
+    /// system sleeps, so the drain over the whole suspend would otherwise be
+    /// divided by the few seconds since the last sample.
+    fn restart(&mut self) {
+        *self = PowerEstimator::default();
+    }
+}
+
+/// How often a log file that keeps failing to be written is reported.
+const WRITE_ERROR_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// A battery supply being logged, with its own power estimation state.
+struct Battery {
+    name: String,
+    path: PathBuf,
+    estimator: PowerEstimator,
+    failures: ReadFailures,
+}
+
+/// Counts consecutive failures to read a battery. The first few are logged
+/// as warnings; once they persist they are logged as an error, repeated at
+/// most every `REPEAT_INTERVAL` instead of on every sample.
+struct ReadFailures {
+    consecutive: u32,
+    repeat: RateLimit,
+}
+
+impl Default for ReadFailures {
+    fn default() -> Self {
+        ReadFailures {
+            consecutive: 0,
+            repeat: RateLimit::new(Self::REPEAT_INTERVAL),
+        }
+    }
+}
+
+impl ReadFailures {
+    /// Consecutive failures after which reading is considered broken.
+    const ESCALATE_AFTER: u32 = 5;
+
+    const REPEAT_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+    fn failed(&mut self, battery: &str, e: &io::Error) {
+        self.consecutive += 1;
+        if self.consecutive < Self::ESCALATE_AFTER {
+            warn!("Failed to read battery info for {}: {}", battery, e);
+        } else if self.repeat.allow().is_some() {
+            error!(
+                "Failed to read battery info for {} {} times in a row: {}",
+                battery, self.consecutive, e
+            );
+        }
+    }
+
+    fn succeeded(&mut self, battery: &str) {
+        if self.consecutive > 0 {
+            info!(
+                "Read battery info for {} again after {} failure(s)",
+                battery, self.consecutive
+            );
+        }
+        self.consecutive = 0;
+        self.repeat.reset();
+    }
+}
+
+/// Reads the sysfs attribute `name` under `dir` (a power supply, hwmon chip,
+/// thermal zone, ...) with surrounding whitespace removed.
+fn read_attr(dir: &Path, name: &str) -> Option<String> {
+    fs::read_to_string(dir.join(name))
+        .ok()
+        .map(|t| t.trim().to_string())
+}
+
+/// Returns the supplies under `<sysfs_root>/class/power_supply` whose `type`
+/// is `Battery`, sorted by name so that the log order is stable.
+///
+/// Supplies with `scope` `Device` power peripherals such as mice or
+/// headsets and are skipped unless `only` names one of them explicitly.
+fn find_batteries(sysfs_root: &Path, only: Option<&str>) -> Vec<Battery> {
+    let mut batteries: Vec<Battery> = fs::read_dir(sysfs_root.join("class/power_supply"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| read_attr(&entry.path(), "type").as_deref() == Some("Battery"))
+        .filter(|entry| match only {
+            Some(name) => entry.file_name().to_str() == Some(name),
+            None => read_attr(&entry.path(), "scope").as_deref() != Some("Device"),
+        })
+        .map(|entry| Battery {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            path: entry.path(),
+            estimator: PowerEstimator::default(),
+            failures: ReadFailures::default(),
+        })
+        .collect();
+
+    batteries.sort_by(|a, b| a.name.cmp(&b.name));
+    batteries
+}
+
+/// Readings shared by every battery row logged in one sample.
+struct SampleContext<'a> {
+    adapter: Option<AdapterState>,
+    /// Scheduled ticks skipped before this sample, e.g. after slow I/O.
+    missed_ticks: u64,
+    /// Kernel boot ID, which changes across reboots but not across suspend
+    /// or hibernate.
+    boot_id: &'a str,
+    cpu_temperature: Option<f64>,
+    rapl: RaplPower,
+    system: SystemState,
+}
+
+/// What the rest of the system was doing at the time of a sample, to help
+/// explain changes in power draw.
+#[derive(Default)]
+struct SystemState {
+    /// Brightness of the first backlight, as a percentage of its maximum.
+    brightness: Option<f64>,
+    /// Current frequency averaged over all CPUs, in MHz.
+    cpu_freq_mhz: Option<f64>,
+    /// 1-minute load average.
+    load: Option<f64>,
+}
+
+impl SystemState {
+    fn read(sysfs_root: &Path, proc_root: &Path) -> Self {
+        let mut backlights: Vec<PathBuf> = fs::read_dir(sysfs_root.join("class/backlight"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .collect();
+        backlights.sort();
+        let brightness = backlights.first().and_then(|backlight| {
+            let brightness = read_sysfs_value(backlight, "brightness")?;
+            let max = read_sysfs_value(backlight, "max_brightness")?;
+            (max > 0.0).then(|| brightness / max * 100.0)
+        });
+
+        // `scaling_cur_freq` is in kHz.
+        let frequencies: Vec<f64> = fs::read_dir(sysfs_root.join("devices/system/cpu"))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| {
+                entry.file_name().to_str().is_some_and(|name| {
+                    name.strip_prefix("cpu")
+                        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+                })
+            })
+            .filter_map(|entry| read_sysfs_value(&entry.path().join("cpufreq"), "scaling_cur_freq"))
+            .collect();
+        let cpu_freq_mhz = (!frequencies.is_empty())
+            .then(|| frequencies.iter().sum::<f64>() / frequencies.len() as f64 / 1000.0);
+
+        let load = fs::read_to_string(proc_root.join("loadavg"))
+            .ok()
+            .and_then(|loadavg| loadavg.split_whitespace().next()?.parse().ok());
+
+        SystemState {
+            brightness,
+            cpu_freq_mhz,
+            load,
+        }
+    }
 }
 
-fn find_battery_path() -> Option<PathBuf> {
-    for name in ["BAT0", "BAT1", "BATT"] {
-        let path = PathBuf::from(format!("/sys/class/power_supply/{}", name));
-        if path.exists() {
-            return Some(path);
+/// Finds the temperature input named by `sensor` under `<sysfs_root>/class`:
+/// a hwmon chip, optionally with a channel label (e.g. `coretemp/Package id 0`
+/// or `k10temp/Tctl`), or the type of a thermal zone (e.g. `x86_pkg_temp`).
+/// An absolute path is used as is.
+fn find_cpu_sensor(sysfs_root: &Path, sensor: &str) -> Option<PathBuf> {
+    if sensor.starts_with('/') {
+        return Some(PathBuf::from(sensor));
+    }
+
+    let (chip, label) = match sensor.split_once('/') {
+        Some((chip, label)) => (chip, Some(label)),
+        None => (sensor, None),
+    };
+    let mut chips: Vec<PathBuf> = fs::read_dir(sysfs_root.join("class/hwmon"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| read_attr(path, "name").as_deref() == Some(chip))
+        .collect();
+    chips.sort();
+
+    for chip_path in chips {
+        let mut inputs: Vec<PathBuf> = fs::read_dir(&chip_path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("temp") && name.ends_with("_input"))
+            })
+            .collect();
+        inputs.sort();
+
+        let found = inputs.into_iter().find(|input| match label {
+            Some(label) => {
+                let name = input.file_name().unwrap_or_default().to_string_lossy();
+                let label_name = name.replace("_input", "_label");
+                read_attr(&chip_path, &label_name).as_deref() == Some(label)
+            }
+            None => true,
+        });
+        if found.is_some() {
+            return found;
         }
     }
-    None
+
+    fs::read_dir(sysfs_root.join("class/thermal"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| read_attr(path, "type").as_deref() == Some(sensor))
+        .map(|zone| zone.join("temp"))
+}
+
+/// Reads a temperature reported in millidegrees Celsius.
+fn read_millidegrees(path: &Path) -> Option<f64> {
+    let value: f64 = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    Some(value / 1000.0)
+}
+
+/// Reads the random ID the kernel generates on every boot from
+/// `<proc_root>/sys/kernel/random/boot_id`, or an empty string if it is not
+/// available.
+fn read_boot_id(proc_root: &Path) -> String {
+    fs::read_to_string(proc_root.join("sys/kernel/random/boot_id"))
+        .map(|id| id.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// State of the external power supplies.
+struct AdapterState {
+    /// `Offline`, or the kind of the online supply (`Mains`, `USB-PD`, ...).
+    kind: String,
+    voltage_max: Option<f64>,
+}
+
+/// Extracts the negotiated mode from `usb_type`, which lists every supported
+/// mode with the active one in brackets, e.g. `C [PD] PD_PPS`.
+fn active_usb_type(usb_type: &str) -> Option<&str> {
+    usb_type
+        .split_whitespace()
+        .find_map(|t| t.strip_prefix('[')?.strip_suffix(']'))
+}
+
+/// Reads the `Mains` and `USB` supplies under `<sysfs_root>/class/power_supply`.
+///
+/// Returns `None` when the system exposes no adapter at all, so that the log
+/// can tell "unknown" apart from "unplugged".
+fn read_adapter_state(sysfs_root: &Path) -> Option<AdapterState> {
+    let mut adapters: Vec<(PathBuf, String)> = fs::read_dir(sysfs_root.join("class/power_supply"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let supply_type = read_attr(&entry.path(), "type")?;
+            (supply_type == "Mains" || supply_type.starts_with("USB"))
+                .then(|| (entry.path(), supply_type))
+        })
+        .collect();
+
+    if adapters.is_empty() {
+        return None;
+    }
+    adapters.sort();
+
+    let online = adapters
+        .iter()
+        .find(|(path, _)| read_attr(path, "online").as_deref() == Some("1"));
+
+    let Some((path, supply_type)) = online else {
+        return Some(AdapterState {
+            kind: "Offline".to_string(),
+            voltage_max: None,
+        });
+    };
+
+    let kind = match read_attr(path, "usb_type") {
+        Some(usb_type) => match active_usb_type(&usb_type) {
+            Some("PD") => "USB-PD".to_string(),
+            Some(mode) => format!("USB-{}", mode.replace('_', "-")),
+            None => "USB".to_string(),
+        },
+        None => supply_type.replace('_', "-"),
+    };
+
+    Some(AdapterState {
+        kind,
+        voltage_max: read_sysfs_micro(path, "voltage_max"),
+    })
+}
+
+fn read_sysfs_value(dir: &Path, name: &str) -> Option<f64> {
+    fs::read_to_string(dir.join(name))
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()
+}
+
+/// Reads a sysfs attribute reported in micro-units (µV, µA, µWh, µAh) and
+/// converts it to the base unit.
+fn read_sysfs_micro(battery_path: &Path, name: &str) -> Option<f64> {
+    read_sysfs_value(battery_path, name).map(|v| v / 1_000_000.0)
+}
+
+/// Reads power in watts, falling back from `power_now` to `current_now` ×
+/// `voltage_now` and finally to the rate of change of `energy_now` or
+/// `charge_now` between samples.
+fn read_power(
+    battery_path: &Path,
+    info: &BatteryInfo,
+    estimator: &mut PowerEstimator,
+) -> (f64, PowerSource) {
+    if let Some(power_w) = read_sysfs_micro(battery_path, "power_now") {
+        return (power_w.abs(), PowerSource::PowerNow);
+    }
+
+    if let (Some(current_a), Some(voltage_v)) = (info.current, info.voltage) {
+        return (current_a.abs() * voltage_v, PowerSource::CurrentVoltage);
+    }
+
+    let counter = if let Some(energy_wh) = info.energy_now {
+        Some((PowerSource::EnergyDelta, energy_wh, 1.0))
+    } else {
+        info.charge_now.and_then(|charge_ah| {
+            info.voltage
+                .or_else(|| read_sysfs_micro(battery_path, "voltage_min_design"))
+                .map(|volts| (PowerSource::ChargeDelta, charge_ah, volts))
+        })
+    };
+
+    if let Some((source, value, volts)) = counter
+        && let Some(watts) = estimator.update(Instant::now(), source, value, volts)
+    {
+        return (watts, source);
+    }
+
+    (0.0, PowerSource::Unavailable)
 }
 
-fn read_battery_info(battery_path: &PathBuf) -> io::Result<BatteryInfo> {
+fn read_battery_info(
+    battery: &mut Battery,
+    timestamp: &str,
+    with_temperature: bool,
+) -> io::Result<BatteryInfo> {
+    let battery_path = battery.path.as_path();
+
     let status = fs::read_to_string(battery_path.join("status"))?
         .trim()
         .to_string();
@@ -42,172 +503,1308 @@ fn read_battery_info(battery_path: &PathBuf) -> io::Result<BatteryInfo> {
         .parse()
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
-    let power_uw: u64 = fs::read_to_string(battery_path.join("power_now"))
-        .unwrap_or_else(|_| "0".to_string())
-        .trim()
-        .parse()
-        .unwrap_or(0);
-    let power_watts = power_uw as f64 / 1_000_000.0;
-
-    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-
-    Ok(BatteryInfo {
-        timestamp,
+    let mut info = BatteryInfo {
+        timestamp: timestamp.to_string(),
+        battery: battery.name.clone(),
         status,
         capacity,
-        power_watts,
-    })
+        power_watts: 0.0,
+        power_source: PowerSource::Unavailable,
+        voltage: read_sysfs_micro(battery_path, "voltage_now"),
+        current: read_sysfs_micro(battery_path, "current_now"),
+        energy_now: read_sysfs_micro(battery_path, "energy_now"),
+        energy_full: read_sysfs_micro(battery_path, "energy_full"),
+        charge_now: read_sysfs_micro(battery_path, "charge_now"),
+        charge_full: read_sysfs_micro(battery_path, "charge_full"),
+        // `temp` is in tenths of a degree Celsius.
+        temperature: with_temperature
+            .then(|| read_sysfs_value(battery_path, "temp"))
+            .flatten()
+            .map(|t| t / 10.0),
+    };
+
+    (info.power_watts, info.power_source) = read_power(battery_path, &info, &mut battery.estimator);
+
+    Ok(info)
+}
+
+/// Reads the wear indicators of `battery`, or `None` if it reports none.
+fn read_health(battery: &Battery, date: NaiveDate) -> Option<HealthRecord> {
+    let path = battery.path.as_path();
+    let record = HealthRecord {
+        date,
+        battery: battery.name.clone(),
+        energy_full: read_sysfs_micro(path, "energy_full"),
+        energy_full_design: read_sysfs_micro(path, "energy_full_design"),
+        charge_full: read_sysfs_micro(path, "charge_full"),
+        charge_full_design: read_sysfs_micro(path, "charge_full_design"),
+        // Firmware that does not count cycles reports 0.
+        cycle_count: read_sysfs_value(path, "cycle_count")
+            .map(|count| count as u32)
+            .filter(|&count| count > 0),
+    };
+
+    let reported = record.full_capacity().is_some() || record.cycle_count.is_some();
+    reported.then_some(record)
+}
+
+/// Appends a row per battery to the health log once a day, and sooner when
+/// its readings change, e.g. after the firmware recalibrates. All batteries
+/// share the one log; the `Battery` column tells their histories apart.
+struct HealthLog {
+    path: PathBuf,
+    /// Latest row logged for each battery, including by earlier sessions.
+    latest: HashMap<String, HealthRecord>,
+    last_check: Option<Instant>,
+}
+
+impl HealthLog {
+    /// Wear readings change slowly, so they are not read on every sample.
+    const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+    fn open(path: PathBuf) -> Self {
+        let latest = read_health_log(&path)
+            .into_iter()
+            .map(|record| (record.battery.clone(), record))
+            .collect();
+        HealthLog {
+            path,
+            latest,
+            last_check: None,
+        }
+    }
+
+    fn check(&mut self, batteries: &[Battery], today: NaiveDate) -> io::Result<()> {
+        if self
+            .last_check
+            .is_some_and(|last| last.elapsed() < Self::CHECK_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.last_check = Some(Instant::now());
+
+        let new_records: Vec<HealthRecord> = batteries
+            .iter()
+            .filter_map(|battery| read_health(battery, today))
+            .filter(|record| {
+                self.latest
+                    .get(&record.battery)
+                    .is_none_or(|latest| latest.date != today || !latest.same_readings(record))
+            })
+            .collect();
+        if new_records.is_empty() {
+            return Ok(());
+        }
+
+        append_csv(&self.path, &new_records)?;
+        for record in new_records {
+            debug!("Logged health of {}", record.battery);
+            self.latest.insert(record.battery.clone(), record);
+        }
+        Ok(())
+    }
+}
+
+/// Appends `records` to the CSV file at `path`, writing the header first if
+/// the file is new.
+fn append_csv<T: Serialize>(path: &Path, records: &[T]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let new_file = !path.exists();
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(new_file)
+        .from_writer(file);
+    for record in records {
+        writer.serialize(record)?;
+    }
+    writer.flush()
+}
+
+/// Power to share among processes. Battery power covers the whole system,
+/// but only while it discharges; otherwise it is what flows into the battery,
+/// so fall back to what RAPL measured for the CPU package and memory.
+fn process_power(summary: &SampleSummary, rapl: &RaplPower) -> Option<f64> {
+    if summary.discharging {
+        return Some(summary.total_power);
+    }
+    rapl.package
+        .map(|package| package + rapl.dram.unwrap_or(0.0))
+}
+
+/// Logs the processes that used the most energy in a window.
+fn log_top_processes(
+    config: &Config,
+    now: DateTime<Local>,
+    window: Duration,
+    usage: Vec<ProcessUsage>,
+) -> io::Result<()> {
+    if usage.is_empty() {
+        return Ok(());
+    }
+    let round = |value: f64| (value * 100.0).round() / 100.0;
+    let time = now.format("%Y-%m-%d %H:%M:%S").to_string();
+    let records: Vec<ProcessRecord> = usage
+        .into_iter()
+        .map(|usage| ProcessRecord {
+            time: time.clone(),
+            window_secs: round(window.as_secs_f64()),
+            pid: usage.pid,
+            command: usage.command,
+            cpu_secs: round(usage.cpu_secs),
+            energy_joules: round(usage.energy_joules),
+        })
+        .collect();
+    append_csv(&get_process_log_path(config, now.date_naive()), &records)
 }
 
 fn get_pid_path() -> PathBuf {
-    if let Ok(runtime_dir) = std::env::var("XDG_RUNTIME_DIR") {
-        PathBuf::from(runtime_dir).join("watt-monitor.pid")
+    get_runtime_dir().join("daemon.pid")
+}
+
+/// How long `stop` waits for the daemon to exit after SIGTERM.
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Tries to take an advisory lock without blocking; returns `false` if
+/// another process holds a conflicting one.
+fn try_flock(file: &File, operation: libc::c_int) -> io::Result<bool> {
+    // SAFETY: the descriptor stays open for as long as `file` is borrowed.
+    if unsafe { libc::flock(file.as_raw_fd(), operation | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    if err.kind() == io::ErrorKind::WouldBlock {
+        Ok(false)
     } else {
-        PathBuf::from("/tmp/watt-monitor.pid")
+        Err(err)
+    }
+}
+
+/// Exclusive lock on the PID file, held for as long as the daemon runs.
+///
+/// The kernel drops the lock when the process exits, even after a crash, so
+/// a stale file or a reused PID never keeps a new daemon from starting.
+struct PidLock {
+    file: File,
+}
+
+impl PidLock {
+    fn acquire(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        if !try_flock(&file, libc::LOCK_EX)? {
+            let mut pid = String::new();
+            file.read_to_string(&mut pid)?;
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Daemon is already running (PID {})", pid.trim()),
+            ));
+        }
+
+        file.set_len(0)?;
+        write!(file, "{}", std::process::id())?;
+        Ok(PidLock { file })
+    }
+}
+
+impl Drop for PidLock {
+    fn drop(&mut self) {
+        self.file.set_len(0).ok();
     }
 }
 
-fn is_already_running(pid_path: &PathBuf) -> bool {
-    if let Ok(content) = fs::read_to_string(pid_path) {
-        if let Ok(pid) = content.trim().parse::<u32>() {
-            return PathBuf::from(format!("/proc/{}", pid)).exists();
+/// Returns the PID of the running daemon, or `None` if nothing holds the
+/// lock on `path`.
+fn running_pid(path: &Path) -> io::Result<Option<libc::pid_t>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if try_flock(&file, libc::LOCK_SH)? {
+        return Ok(None);
+    }
+
+    // A daemon that just took the lock empties the file before writing its
+    // PID, so give it a moment.
+    let deadline = Instant::now() + Duration::from_secs(1);
+    loop {
+        let content = fs::read_to_string(path)?;
+        if let Ok(pid) = content.trim().parse() {
+            return Ok(Some(pid));
+        }
+        // Or it is stopping and has emptied the file already.
+        if try_flock(&file, libc::LOCK_SH)? {
+            return Ok(None);
+        }
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Daemon is running but {:?} holds no PID", path),
+            ));
         }
+        thread::sleep(Duration::from_millis(50));
     }
-    false
 }
 
-fn create_pid_file(pid_path: &PathBuf) -> io::Result<()> {
-    let pid = std::process::id();
-    fs::write(pid_path, pid.to_string())?;
+/// Prints whether the daemon is running and its latest sample; returns
+/// whether it is running.
+pub fn status(config: &Config) -> io::Result<bool> {
+    let Some(pid) = running_pid(&get_pid_path())? else {
+        println!("Daemon is not running");
+        return Ok(false);
+    };
+
+    println!("Daemon is running (PID {})", pid);
+    println!("Log file: {:?}", config.live_log);
+    if let Ok(current) = ipc::request(&get_socket_path(), &Request::Current) {
+        for record in current.records {
+            println!(
+                "Last sample at {}: {}{}, {:.0}%, {:.2} W",
+                record.time.format("%H:%M:%S"),
+                record
+                    .battery
+                    .map(|name| format!("{} ", name))
+                    .unwrap_or_default(),
+                record.status,
+                record.capacity,
+                record.power
+            );
+        }
+    }
+    Ok(true)
+}
+
+/// Sends SIGTERM to the running daemon and waits for it to exit.
+pub fn stop() -> io::Result<()> {
+    let pid_path = get_pid_path();
+    let Some(pid) = running_pid(&pid_path)? else {
+        println!("Daemon is not running");
+        return Ok(());
+    };
+
+    // SAFETY: kill has no memory-safety preconditions.
+    if unsafe { libc::kill(pid, SIGTERM) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let deadline = Instant::now() + STOP_TIMEOUT;
+    while running_pid(&pid_path)?.is_some() {
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "Daemon (PID {}) did not stop within {:?}",
+                    pid, STOP_TIMEOUT
+                ),
+            ));
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    println!("Daemon stopped (PID {})", pid);
     Ok(())
 }
 
-fn remove_pid_file(pid_path: &PathBuf) {
-    fs::remove_file(pid_path).ok();
+/// Attributes the battery does not expose are written as empty fields.
+fn format_optional(value: Option<f64>, precision: usize) -> String {
+    value
+        .map(|v| format!("{:.*}", precision, v))
+        .unwrap_or_default()
+}
+
+fn format_csv_record(info: &BatteryInfo, context: &SampleContext) -> String {
+    let adapter = context.adapter.as_ref();
+
+    format!(
+        "{},{},{},{:.2},{},{},{},{},{},{},{},{},{},{},{},,,{},{},{},{},{},{},{},{},{},{}",
+        info.timestamp,
+        info.status,
+        info.capacity,
+        info.power_watts,
+        info.power_source.as_str(),
+        format_optional(info.voltage, 3),
+        format_optional(info.current, 3),
+        format_optional(info.energy_now, 3),
+        format_optional(info.energy_full, 3),
+        format_optional(info.charge_now, 3),
+        format_optional(info.charge_full, 3),
+        info.battery,
+        adapter.map(|a| a.kind.as_str()).unwrap_or_default(),
+        format_optional(adapter.and_then(|a| a.voltage_max), 1),
+        context.missed_ticks,
+        context.boot_id,
+        format_optional(info.temperature, 1),
+        format_optional(context.cpu_temperature, 1),
+        format_optional(context.rapl.package, 2),
+        format_optional(context.rapl.core, 2),
+        format_optional(context.rapl.uncore, 2),
+        format_optional(context.rapl.dram, 2),
+        format_optional(context.system.brightness, 0),
+        format_optional(context.system.cpu_freq_mhz, 0),
+        format_optional(context.system.load, 2),
+    )
+}
+
+/// Formats an event row. Every sample column is left empty, so readers that
+/// predate events skip the row as unparseable.
+fn format_csv_event(
+    timestamp: &str,
+    event: &str,
+    suspended: Option<Duration>,
+    boot_id: &str,
+) -> String {
+    let columns: Vec<&str> = CSV_HEADER.split(',').collect();
+    let mut fields = vec![String::new(); columns.len()];
+
+    for (column, field) in columns.iter().zip(fields.iter_mut()) {
+        match *column {
+            "Time" => *field = timestamp.to_string(),
+            "Event" => *field = event.to_string(),
+            "Suspended(s)" => {
+                *field = suspended
+                    .map(|d| d.as_secs().to_string())
+                    .unwrap_or_default()
+            }
+            "BootId" => *field = boot_id.to_string(),
+            _ => {}
+        }
+    }
+
+    fields.join(",")
+}
+
+/// How long a subscriber may leave a row unread before it is dropped.
+const SUBSCRIBER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Rows waiting to be appended to the live log.
+///
+/// Rows are kept in memory between flushes to spare the disk. The buffer is
+/// shared with the IPC server, which answers requests for recent samples and
+/// streams every new row to subscribed clients.
+#[derive(Clone, Default)]
+pub struct LogBuffer {
+    state: Arc<Mutex<BufferState>>,
+    /// Held while writing the live log, so that flushes and rotations do not
+    /// interleave. Rows can still be added meanwhile.
+    writing: Arc<Mutex<()>>,
+}
+
+#[derive(Default)]
+struct BufferState {
+    unflushed: VecDeque<String>,
+    /// Rows taken out of `unflushed` by a flush that is still writing them.
+    flushing: Arc<Vec<String>>,
+    /// Latest sample rows, kept for `last` requests.
+    recent: VecDeque<String>,
+    /// Rows queued for each subscriber's writer thread.
+    subscribers: Vec<SyncSender<String>>,
+    /// Rows dropped since the last successful flush.
+    dropped: u64,
 }
 
-fn write_csv_record(info: &BatteryInfo) -> io::Result<()> {
-    let log_path = get_today_log_path();
+impl LogBuffer {
+    /// Rows held at most; the oldest are dropped only while flushing fails.
+    const CAPACITY: usize = 4096;
+
+    /// Sample rows kept for `last` requests.
+    const RECENT: usize = 1024;
+
+    /// Adds a sample row and returns whether the buffer is now full.
+    fn push_sample(&self, row: String) -> bool {
+        self.push(row, true)
+    }
+
+    /// Adds an event row and returns whether the buffer is now full.
+    fn push_event(&self, row: String) -> bool {
+        self.push(row, false)
+    }
+
+    fn push(&self, row: String, sample: bool) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        if !state.subscribers.is_empty()
+            && let Some(json) = csv_row_to_json(CSV_HEADER, &row)
+        {
+            // Clients that fall this far behind or went away are dropped
+            // rather than allowed to hold rows without bound.
+            state
+                .subscribers
+                .retain(|rows| rows.try_send(json.clone()).is_ok());
+        }
+
+        if sample {
+            if state.recent.len() == Self::RECENT {
+                state.recent.pop_front();
+            }
+            state.recent.push_back(row.clone());
+        }
+
+        if state.unflushed.len() == Self::CAPACITY {
+            state.unflushed.pop_front();
+            if state.dropped == 0 {
+                warn!("Log buffer full, dropping the oldest rows until the log can be written");
+            }
+            state.dropped += 1;
+        }
+        state.unflushed.push_back(row);
+        state.unflushed.len() == Self::CAPACITY
+    }
+
+    fn flush(&self, log_path: &Path) -> io::Result<()> {
+        self.flush_then(log_path, || Ok(()))
+    }
+
+    /// Appends the buffered rows to `log_path`, then runs `then` (e.g. a
+    /// rotation) before any other flush can write to the log.
+    ///
+    /// The rows are taken out of the buffer before writing them, so adding
+    /// rows never waits on the disk.
+    fn flush_then<T>(
+        &self,
+        log_path: &Path,
+        then: impl FnOnce() -> io::Result<T>,
+    ) -> io::Result<T> {
+        let _writing = self.writing.lock().unwrap();
+        let rows = {
+            let mut state = self.state.lock().unwrap();
+            state.flushing = Arc::new(Vec::from(std::mem::take(&mut state.unflushed)));
+            Arc::clone(&state.flushing)
+        };
+
+        if !rows.is_empty()
+            && let Err(e) = append_rows(log_path, &rows)
+        {
+            self.restore(&rows);
+            return Err(e);
+        }
+
+        {
+            let mut state = self.state.lock().unwrap();
+            state.flushing = Arc::default();
+            if !rows.is_empty() {
+                debug!("Flushed {} row(s) to {:?}", rows.len(), log_path);
+            }
+            if state.dropped > 0 {
+                warn!(
+                    "Dropped {} row(s) while the log could not be written",
+                    state.dropped
+                );
+                state.dropped = 0;
+            }
+        }
+        then()
+    }
+
+    /// Puts rows that could not be written back in front of those added
+    /// since, dropping the oldest beyond the capacity.
+    fn restore(&self, rows: &[String]) {
+        let mut state = self.state.lock().unwrap();
+        state.flushing = Arc::default();
+        let added = std::mem::take(&mut state.unflushed);
+        let mut unflushed: VecDeque<String> = rows.iter().cloned().chain(added).collect();
+        let excess = unflushed.len().saturating_sub(Self::CAPACITY);
+        if excess > 0 {
+            if state.dropped == 0 {
+                warn!("Log buffer full, dropping the oldest rows until the log can be written");
+            }
+            state.dropped += excess as u64;
+            unflushed.drain(..excess);
+        }
+        state.unflushed = unflushed;
+    }
+
+    /// Returns the rows of the latest sample, one per battery, as JSON.
+    pub fn current_sample(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        let time = |row: &str| row.split(',').next().unwrap_or_default().to_string();
+        let Some(latest) = state.recent.back().map(|row| time(row)) else {
+            return vec![];
+        };
+
+        let mut rows: Vec<String> = state
+            .recent
+            .iter()
+            .rev()
+            .take_while(|row| time(row) == latest)
+            .filter_map(|row| csv_row_to_json(CSV_HEADER, row))
+            .collect();
+        rows.reverse();
+        rows
+    }
+
+    /// Returns the last `count` sample rows as JSON, oldest first.
+    pub fn last_samples(&self, count: usize) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        let skip = state.recent.len().saturating_sub(count);
+        state
+            .recent
+            .iter()
+            .skip(skip)
+            .filter_map(|row| csv_row_to_json(CSV_HEADER, row))
+            .collect()
+    }
+
+    /// Sends the rows not flushed yet to `stream`, then every row added from
+    /// now on. The rows are written on a thread of their own, so a client
+    /// that stops reading never blocks the sampling loop.
+    pub fn subscribe(&self, stream: UnixStream) -> io::Result<()> {
+        stream.set_write_timeout(Some(SUBSCRIBER_TIMEOUT))?;
+        let (sender, rows) = mpsc::sync_channel(Self::CAPACITY);
+        let backlog: Vec<String> = {
+            let mut state = self.state.lock().unwrap();
+            state.subscribers.push(sender);
+            state
+                .flushing
+                .iter()
+                .chain(state.unflushed.iter())
+                .filter_map(|row| csv_row_to_json(CSV_HEADER, row))
+                .collect()
+        };
+
+        thread::spawn(move || {
+            let mut stream = stream;
+            for row in backlog.into_iter().chain(rows) {
+                if writeln!(stream, "{}", row).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(())
+    }
+}
 
-    if !log_path.exists() {
-        let mut file = File::create(&log_path)?;
+/// Appends `rows` to the log at `path`, starting a new log with the header.
+fn append_rows(path: &Path, rows: &[String]) -> io::Result<()> {
+    let new_file = !path.exists();
+    let mut file = BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?);
+    if new_file {
         writeln!(file, "{}", CSV_HEADER)?;
     }
+    for row in rows {
+        writeln!(file, "{}", row)?;
+    }
+    file.flush()
+}
 
-    let mut file = OpenOptions::new().append(true).open(&log_path)?;
+/// Path of the temporary file used while atomically replacing `path`.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
 
-    writeln!(
-        file,
-        "{},{},{},{:.2}",
-        info.timestamp, info.status, info.capacity, info.power_watts
-    )?;
+/// Replaces `path` with `lines` under the current header. The content is
+/// written to a temporary file next to it, synced and renamed over the
+/// original, so a crash leaves either the old or the new file in place.
+fn write_atomically<'a>(path: &Path, lines: impl IntoIterator<Item = &'a str>) -> io::Result<()> {
+    let tmp_path = temp_path(path);
+    let mut file = BufWriter::new(File::create(&tmp_path)?);
+    writeln!(file, "{}", CSV_HEADER)?;
+    for line in lines {
+        writeln!(file, "{}", line)?;
+    }
+    file.into_inner()?.sync_all()?;
+    fs::rename(&tmp_path, path)?;
 
+    // Persist the rename itself.
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
     Ok(())
 }
 
-fn rotate_archive(date: NaiveDate) -> io::Result<()> {
-    let log_path = get_today_log_path();
-    if !log_path.exists() {
-        return Ok(());
+/// Date of a CSV row, taken from its leading timestamp.
+fn row_date(line: &str) -> Option<NaiveDate> {
+    line.get(..10)
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+}
+
+/// How `rotate_archive` adds rows to an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArchiveWrite {
+    /// Appends the rows, checking only the end of the archive for rows that
+    /// an interrupted rotation already added. Used while running, so that a
+    /// checkpoint writes no more than the rows it moves.
+    Append,
+    /// Rewrites the whole archive without any duplicate rows. Used to
+    /// recover at startup.
+    Rewrite,
+}
+
+/// Appends `rows` to the archive at `path`, skipping those already at its
+/// end. Returns `false` without writing anything when the archive starts
+/// with an older header, which only a rewrite can update.
+fn append_to_archive(path: &Path, rows: &[&str]) -> io::Result<bool> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)?;
+    let mut len = file.metadata()?.len();
+
+    let mut tail = String::new();
+    if len > 0 {
+        let mut header = String::new();
+        BufReader::new(&file).read_line(&mut header)?;
+        if header.trim_end() != CSV_HEADER {
+            return Ok(false);
+        }
+        // A write cut short by a crash or power loss leaves part of a row
+        // without its newline, which the next row would be appended to.
+        let complete_len = complete_lines_len(&file, len)?;
+        if complete_len < len {
+            warn!(
+                "Dropping {} byte(s) of an incomplete row at the end of {:?}",
+                len - complete_len,
+                path
+            );
+            file.set_len(complete_len)?;
+        }
+        len = complete_len;
+        // Rows added by an interrupted rotation take up at most as many
+        // bytes as these, plus the newline in front of them.
+        let tail_len: u64 = rows.iter().map(|row| row.len() as u64 + 1).sum();
+        file.seek(SeekFrom::Start(len.saturating_sub(tail_len + 1)))?;
+        file.read_to_string(&mut tail)?;
     }
+    // The first line of the tail may be cut off.
+    let present: HashSet<&str> = tail.lines().skip(1).collect();
 
-    let data_dir = get_data_dir();
-    fs::create_dir_all(&data_dir)?;
+    let mut writer = BufWriter::new(&file);
+    if len == 0 {
+        writeln!(writer, "{}", CSV_HEADER)?;
+    }
+    for row in rows.iter().filter(|row| !present.contains(*row)) {
+        writeln!(writer, "{}", row)?;
+    }
+    writer.flush()?;
+    drop(writer);
+    file.sync_all()?;
 
-    let date_str = date.format("%Y-%m-%d").to_string();
-    let archive_path = data_dir.join(format!("{}.csv", date_str));
+    // Persist the entry of a new archive.
+    if len == 0
+        && let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty())
+    {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(true)
+}
 
-    let content = fs::read_to_string(&log_path)?;
-    let lines: Vec<&str> = content.lines().collect();
+/// Length of `file` up to and including its last newline.
+fn complete_lines_len(mut file: &File, len: u64) -> io::Result<u64> {
+    let mut chunk = [0; 4096];
+    let mut end = len;
+    while end > 0 {
+        let start = end.saturating_sub(chunk.len() as u64);
+        let buf = &mut chunk[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(buf)?;
+        if let Some(i) = buf.iter().rposition(|&b| b == b'\n') {
+            return Ok(start + i as u64 + 1);
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// Moves every row of the live log dated before `before` into the archive
+/// for its own date and keeps the rest in the live log.
+///
+/// Archives are updated before the live log and rows already present in an
+/// archive are not added again, so running this again after an interrupted
+/// rotation completes it without duplicating rows.
+fn rotate_archive(config: &Config, before: NaiveDate, write: ArchiveWrite) -> io::Result<()> {
+    let log_path = &config.live_log;
+    let content = match fs::read_to_string(log_path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let mut archived: BTreeMap<NaiveDate, Vec<&str>> = BTreeMap::new();
+    let mut kept = Vec::new();
+    for line in content.lines().skip(1).filter(|l| !l.is_empty()) {
+        match row_date(line) {
+            Some(date) if date < before => archived.entry(date).or_default().push(line),
+            _ => kept.push(line),
+        }
+    }
 
-    if lines.len() <= 1 {
+    if archived.is_empty() {
         return Ok(());
     }
 
-    if !archive_path.exists() {
-        let mut file = File::create(&archive_path)?;
-        writeln!(file, "{}", CSV_HEADER)?;
+    fs::create_dir_all(&config.data_dir)?;
+
+    for (date, rows) in &archived {
+        let archive_path = get_archive_path_for_date(config, *date);
+        if write == ArchiveWrite::Append && append_to_archive(&archive_path, rows)? {
+            info!("Archived data for {}", date.format("%Y-%m-%d"));
+            continue;
+        }
+
+        let existing = match fs::read_to_string(&archive_path) {
+            Ok(existing) => existing,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let old_rows: Vec<&str> = existing.lines().skip(1).collect();
+        let present: HashSet<&str> = old_rows.iter().copied().collect();
+        let new_rows = rows.iter().copied().filter(|row| !present.contains(row));
+
+        write_atomically(&archive_path, old_rows.iter().copied().chain(new_rows))?;
+        info!("Archived data for {}", date.format("%Y-%m-%d"));
     }
 
-    let mut archive_file = OpenOptions::new().append(true).open(&archive_path)?;
+    write_atomically(log_path, kept)
+}
 
-    for line in lines.iter().skip(1) {
-        if line.starts_with(&date_str) {
-            writeln!(archive_file, "{}", line)?;
+/// Cleans up after a previous daemon that did not shut down cleanly: drops
+/// half-written temporary files and archives every row left in the live log,
+/// whichever day it belongs to.
+fn recover_live_log(config: &Config) -> io::Result<()> {
+    let mut stale = vec![temp_path(&config.live_log)];
+    if let Ok(entries) = fs::read_dir(&config.data_dir) {
+        stale.extend(
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.to_string_lossy().ends_with(".csv.tmp")),
+        );
+    }
+    for path in stale {
+        match fs::remove_file(&path) {
+            Ok(()) => info!("Removed incomplete file {:?}", path),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
         }
     }
 
-    let mut file = File::create(&log_path)?;
-    writeln!(file, "{}", CSV_HEADER)?;
+    // Older versions kept the live log directly in the shared temp directory.
+    let legacy_log = std::env::temp_dir().join("battery_watt_history.csv");
+    if legacy_log != config.live_log
+        && fs::symlink_metadata(&legacy_log).is_ok_and(|m| m.is_file() && m.uid() == current_uid())
+    {
+        let legacy = Config {
+            live_log: legacy_log.clone(),
+            ..config.clone()
+        };
+        rotate_archive(&legacy, NaiveDate::MAX, ArchiveWrite::Rewrite)?;
+        fs::remove_file(&legacy_log)?;
+        info!("Archived old live log {:?}", legacy_log);
+    }
+
+    rotate_archive(config, NaiveDate::MAX, ArchiveWrite::Rewrite)
+}
 
-    eprintln!("Archived data for {}", date_str);
+fn get_checkpoint_count_path() -> PathBuf {
+    get_runtime_dir().join("checkpoints")
+}
 
+/// Checkpoints the live log into `data_dir` whenever one of `signals`
+/// arrives, e.g. from the system-sleep hook right before the machine
+/// suspends, so that the samples survive the battery running flat during the
+/// suspend.
+///
+/// The number of checkpoints taken is then written next to the PID file;
+/// the hook waits for it to change before letting the system sleep.
+fn checkpoint_on_signal(mut signals: Signals, buffer: LogBuffer, config: Config) -> io::Result<()> {
+    let count_path = get_checkpoint_count_path();
+    // Counting starts over, so a count left by an earlier daemon must not
+    // match the first one written here.
+    match fs::remove_file(&count_path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    thread::spawn(move || {
+        for (count, _) in (1u64..).zip(signals.forever()) {
+            if let Err(e) = buffer.flush_then(&config.live_log, || {
+                rotate_archive(&config, NaiveDate::MAX, ArchiveWrite::Append)
+            }) {
+                error!("Failed to checkpoint live log: {}", e);
+            }
+            if let Err(e) = fs::write(&count_path, count.to_string()) {
+                error!("Failed to write {:?}: {}", count_path, e);
+            }
+        }
+    });
     Ok(())
 }
 
-pub fn run() -> io::Result<()> {
-    let pid_path = get_pid_path();
+/// Waits for the next sample, pinging the systemd watchdog in between when
+/// the interval is longer than it allows. Returns `true` if shutdown was
+/// requested meanwhile.
+fn wait_for_sample(shutdown: &Shutdown, deadline: Instant, notifier: Option<&Notifier>) -> bool {
+    let Some((notifier, interval)) =
+        notifier.and_then(|n| n.watchdog_interval().map(|interval| (n, interval)))
+    else {
+        return shutdown.wait_until(deadline);
+    };
 
-    if is_already_running(&pid_path) {
-        eprintln!("Error: Daemon is already running");
-        return Err(io::Error::new(
-            io::ErrorKind::AlreadyExists,
-            "Daemon is already running",
-        ));
+    loop {
+        if shutdown.wait_until(deadline.min(Instant::now() + interval)) {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        notifier.watchdog();
+    }
+}
+
+pub fn run(config: &Config) -> io::Result<()> {
+    let options = &config.daemon;
+    let battery = options.battery.as_deref();
+
+    create_runtime_dir(&get_runtime_dir())?;
+    // SIGUSR1 would kill the daemon by default, so it is caught before the
+    // PID file tells the sleep hook where to send it.
+    let checkpoint_signals = Signals::new([SIGUSR1])?;
+    let _pid_lock = PidLock::acquire(&get_pid_path()).inspect_err(|e| error!("{}", e))?;
+
+    let notifier = Notifier::from_env();
+
+    let mut batteries = find_batteries(&options.sysfs_root, battery);
+    if batteries.is_empty() {
+        let message = match battery {
+            Some(name) => format!("Battery {} not found in system", name),
+            None => "No battery found in system".to_string(),
+        };
+        if let Some(notifier) = &notifier {
+            notifier.status(&message);
+        }
+        return Err(io::Error::new(io::ErrorKind::NotFound, message));
     }
 
-    let battery_path = find_battery_path()
-        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No battery found in system"))?;
+    let shutdown = Shutdown::on_signals(&[SIGTERM, SIGINT])?;
+    let buffer = LogBuffer::default();
 
-    create_pid_file(&pid_path)?;
+    let socket_path = get_socket_path();
+    if let Err(e) = ipc::serve(&socket_path, buffer.clone()) {
+        error!("Failed to open IPC socket {:?}: {}", socket_path, e);
+    }
+    let mut scheduler = Scheduler::default();
+    let mut suspend_detector = SuspendDetector::new();
+    let boot_id = read_boot_id(&options.proc_root);
 
-    let running = Arc::new(AtomicBool::new(true));
-    flag::register(SIGTERM, Arc::clone(&running))?;
-    flag::register(SIGINT, Arc::clone(&running))?;
+    // Recovery rewrites archives and removes temporary files that a
+    // checkpoint would be using, so checkpoints start only after it.
+    if let Err(e) = recover_live_log(config) {
+        error!("Failed to recover live log: {}", e);
+    }
+    checkpoint_on_signal(checkpoint_signals, buffer.clone(), config.clone())?;
 
     let mut current_date = Local::now().date_naive();
+    let checkpoint_interval = Duration::from_secs(options.checkpoint_interval_secs);
+    let mut last_checkpoint = Instant::now();
+    let flush_interval = Duration::from_secs(options.flush_interval_secs);
+    let mut last_flush = Instant::now();
 
-    eprintln!(
-        "Daemon started (PID: {}), logging every {} seconds",
-        std::process::id(),
-        LOG_INTERVAL_SECS
-    );
-    eprintln!("Battery path: {:?}", battery_path);
-    eprintln!("Log file: {:?}", get_today_log_path());
-    eprintln!("Press Ctrl+C or send SIGTERM to stop");
+    let mut interval_policy = IntervalPolicy::new(options);
+    let mut write_errors = RateLimit::new(WRITE_ERROR_INTERVAL);
+    let mut health_log = HealthLog::open(get_health_log_path(config));
+    let cpu_sensor = options.cpu_sensor.as_deref().and_then(|sensor| {
+        let path = find_cpu_sensor(&options.sysfs_root, sensor);
+        if path.is_none() {
+            warn!("CPU temperature sensor {:?} not found", sensor);
+        }
+        path
+    });
+    let mut processes = options
+        .processes
+        .then(|| ProcessCollector::new(&options.proc_root));
+    let process_interval = Duration::from_secs(options.process_interval_secs.max(1));
+    let mut rapl = options
+        .rapl
+        .then(|| RaplCollector::discover(&options.sysfs_root))
+        .and_then(|collector| {
+            collector
+                .inspect_err(|e| warn!("Not logging RAPL power: {}", e))
+                .ok()
+        });
+
+    match options.sampling {
+        SamplingMode::Fixed => info!(
+            "Daemon started (PID: {}), logging every {} seconds",
+            std::process::id(),
+            options.log_interval_secs
+        ),
+        SamplingMode::Adaptive => info!(
+            "Daemon started (PID: {}), logging every {}-{} seconds (adaptive)",
+            std::process::id(),
+            options.min_interval_secs,
+            options.max_interval_secs
+        ),
+    }
+    for battery in &batteries {
+        info!("Battery path: {:?}", battery.path);
+    }
+    if let Some(path) = &cpu_sensor {
+        info!("CPU temperature: {:?}", path);
+    }
+    info!("Log file: {:?}", config.live_log);
+    info!("Press Ctrl+C or send SIGTERM to stop");
+
+    // Marks the session start, so that the TUI can tell a gap left by a
+    // crashed or stopped daemon from one left by a suspend.
+    let started = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    buffer.push_event(format_csv_event(&started, "start", None, &boot_id));
+
+    if let Some(notifier) = &notifier {
+        let names: Vec<&str> = batteries.iter().map(|b| b.name.as_str()).collect();
+        notifier.ready(&format!("Logging {}", names.join(", ")));
+    }
+
+    loop {
+        let interval = interval_policy.current();
+        if wait_for_sample(
+            &shutdown,
+            Scheduler::next_deadline(interval),
+            notifier.as_ref(),
+        ) {
+            break;
+        }
+        let missed_ticks = scheduler.tick(Instant::now(), interval);
+        if missed_ticks > 0 {
+            warn!("Missed {} sample(s)", missed_ticks);
+        }
 
-    while running.load(Ordering::Relaxed) {
         let today = Local::now().date_naive();
 
         if today != current_date {
-            if let Err(e) = rotate_archive(current_date) {
-                eprintln!("Failed to rotate archive: {}", e);
+            if let Err(e) = buffer.flush_then(&config.live_log, || {
+                rotate_archive(config, today, ArchiveWrite::Append)
+            }) {
+                error!("Failed to rotate archive: {}", e);
             }
             current_date = today;
+        } else if !checkpoint_interval.is_zero() && last_checkpoint.elapsed() >= checkpoint_interval
+        {
+            if let Err(e) = buffer.flush_then(&config.live_log, || {
+                rotate_archive(config, NaiveDate::MAX, ArchiveWrite::Append)
+            }) {
+                error!("Failed to checkpoint live log: {}", e);
+            }
+            last_checkpoint = Instant::now();
         }
 
-        match read_battery_info(&battery_path) {
-            Ok(info) => {
-                if let Err(e) = write_csv_record(&info) {
-                    eprintln!("Failed to write log: {}", e);
+        let now = Local::now();
+        let timestamp = now.format("%Y-%m-%d %H:%M:%S").to_string();
+
+        if let Some(suspended) = suspend_detector.check() {
+            info!("Resumed after {} seconds of suspend", suspended.as_secs());
+            for battery in &mut batteries {
+                battery.estimator.restart();
+            }
+            if let Some(rapl) = &mut rapl {
+                rapl.restart();
+            }
+            let suspended_at = now - TimeDelta::from_std(suspended).unwrap_or_default();
+            let events = [
+                (
+                    suspended_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    "suspend",
+                    None,
+                ),
+                (timestamp.clone(), "resume", Some(suspended)),
+            ];
+            for (time, event, duration) in events {
+                buffer.push_event(format_csv_event(&time, event, duration, &boot_id));
+            }
+        }
+        let context = SampleContext {
+            adapter: read_adapter_state(&options.sysfs_root),
+            missed_ticks,
+            boot_id: &boot_id,
+            cpu_temperature: cpu_sensor.as_deref().and_then(read_millidegrees),
+            rapl: rapl.as_mut().map(RaplCollector::read).unwrap_or_default(),
+            system: if options.system_state {
+                SystemState::read(&options.sysfs_root, &options.proc_root)
+            } else {
+                SystemState::default()
+            },
+        };
+
+        let mut summary = SampleSummary {
+            total_power: 0.0,
+            total_capacity: 0.0,
+            discharging: false,
+            full_on_ac: context.adapter.as_ref().is_none_or(|a| a.kind != "Offline"),
+        };
+
+        let mut buffer_full = false;
+        for battery in &mut batteries {
+            match read_battery_info(battery, &timestamp, options.battery_temperature) {
+                Ok(info) => {
+                    battery.failures.succeeded(&battery.name);
+                    debug!(
+                        "{}: {}, {}%, {:.2} W",
+                        battery.name, info.status, info.capacity, info.power_watts
+                    );
+                    summary.total_power += info.power_watts;
+                    summary.total_capacity += info.capacity as f64;
+                    summary.discharging |= info.status == "Discharging";
+                    summary.full_on_ac &= matches!(info.status.as_str(), "Full" | "Not charging");
+
+                    buffer_full |= buffer.push_sample(format_csv_record(&info, &context));
                 }
+                Err(e) => battery.failures.failed(&battery.name, &e),
             }
-            Err(e) => {
-                eprintln!("Failed to read battery info: {}", e);
+        }
+
+        if let Err(e) = health_log.check(&batteries, today) {
+            error!("Failed to write health log: {}", e);
+        }
+
+        if let Some(processes) = &mut processes {
+            processes.sample(process_power(&summary, &context.rapl));
+            if let Some((window, top)) = processes.take_top(process_interval, options.top_processes)
+                && let Err(e) = log_top_processes(config, now, window, top)
+            {
+                error!("Failed to write process log: {}", e);
             }
         }
 
-        thread::sleep(Duration::from_secs(LOG_INTERVAL_SECS));
+        interval_policy.update(&summary);
+
+        if let Some(notifier) = &notifier {
+            notifier.watchdog();
+            notifier.status(&format!(
+                "Last sample at {}, {:.2} W",
+                now.format("%H:%M:%S"),
+                summary.total_power
+            ));
+        }
+
+        if buffer_full || last_flush.elapsed() >= flush_interval {
+            if let Err(e) = buffer.flush(&config.live_log) {
+                match write_errors.allow() {
+                    Some(0) => error!("Failed to write log: {}", e),
+                    Some(repeated) => error!(
+                        "Failed to write log: {} ({} more failures since the last report)",
+                        e, repeated
+                    ),
+                    None => {}
+                }
+            } else {
+                write_errors.reset();
+            }
+            last_flush = Instant::now();
+        }
     }
 
-    eprintln!("\nShutting down...");
+    info!("Shutting down...");
+    if let Some(notifier) = &notifier {
+        notifier.stopping();
+    }
 
-    if let Err(e) = rotate_archive(current_date) {
-        eprintln!("Failed to rotate archive on shutdown: {}", e);
+    if let Err(e) = buffer.flush_then(&config.live_log, || {
+        rotate_archive(config, NaiveDate::MAX, ArchiveWrite::Append)
+    }) {
+        error!("Failed to rotate archive on shutdown: {}", e);
     }
 
-    remove_pid_file(&pid_path);
-    eprintln!("Daemon stopped");
+    fs::remove_file(&socket_path).ok();
+    info!("Daemon stopped");
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_watts(actual: Option<f64>, expected: f64) {
+        let actual = actual.expect("an estimate");
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {} W, got {} W",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn estimator_needs_two_readings() {
+        let mut estimator = PowerEstimator::default();
+        let start = Instant::now();
+        assert_eq!(
+            estimator.update(start, PowerSource::EnergyDelta, 50.0, 1.0),
+            None
+        );
+    }
+
+    #[test]
+    fn estimator_derives_power_from_moving_energy_counter() {
+        let mut estimator = PowerEstimator::default();
+        let start = Instant::now();
+        estimator.update(start, PowerSource::EnergyDelta, 50.0, 1.0);
+
+        // 0.1 Wh in 36 seconds is 10 W.
+        let later = start + Duration::from_secs(36);
+        assert_watts(
+            estimator.update(later, PowerSource::EnergyDelta, 49.9, 1.0),
+            10.0,
+        );
+    }
+
+    #[test]
+    fn estimator_converts_charge_with_voltage() {
+        let mut estimator = PowerEstimator::default();
+        let start = Instant::now();
+        estimator.update(start, PowerSource::ChargeDelta, 4.0, 12.0);
+
+        // 0.01 Ah at 12 V in 36 seconds is 12 W.
+        let later = start + Duration::from_secs(36);
+        assert_watts(
+            estimator.update(later, PowerSource::ChargeDelta, 3.99, 12.0),
+            12.0,
+        );
+    }
+
+    #[test]
+    fn estimator_keeps_estimate_until_counter_goes_stale() {
+        let mut estimator = PowerEstimator::default();
+        let start = Instant::now();
+        estimator.update(start, PowerSource::EnergyDelta, 50.0, 1.0);
+        let moved = start + Duration::from_secs(36);
+        estimator.update(moved, PowerSource::EnergyDelta, 49.9, 1.0);
+
+        let unchanged = moved + Duration::from_secs(30);
+        assert_watts(
+            estimator.update(unchanged, PowerSource::EnergyDelta, 49.9, 1.0),
+            10.0,
+        );
+
+        let stale = moved + PowerEstimator::STALE_AFTER;
+        assert_watts(
+            estimator.update(stale, PowerSource::EnergyDelta, 49.9, 1.0),
+            0.0,
+        );
+    }
+
+    #[test]
+    fn estimator_starts_over_after_restart() {
+        let mut estimator = PowerEstimator::default();
+        let start = Instant::now();
+        estimator.update(start, PowerSource::EnergyDelta, 50.0, 1.0);
+        estimator.restart();
+
+        let resumed = start + Duration::from_secs(4);
+        assert_eq!(
+            estimator.update(resumed, PowerSource::EnergyDelta, 40.0, 1.0),
+            None
+        );
+    }
+
+    #[test]
+    fn estimator_starts_over_when_source_changes() {
+        let mut estimator = PowerEstimator::default();
+        let start = Instant::now();
+        estimator.update(start, PowerSource::EnergyDelta, 50.0, 1.0);
+
+        let later = start + Duration::from_secs(36);
+        assert_eq!(
+            estimator.update(later, PowerSource::ChargeDelta, 4.0, 12.0),
+            None
+        );
+    }
+
+    fn summary(discharging: bool) -> SampleSummary {
+        SampleSummary {
+            total_power: 20.0,
+            total_capacity: 50.0,
+            discharging,
+            full_on_ac: false,
+        }
+    }
+
+    #[test]
+    fn processes_share_battery_power_while_discharging() {
+        let rapl = RaplPower {
+            package: Some(5.0),
+            ..RaplPower::default()
+        };
+        assert_eq!(process_power(&summary(true), &rapl), Some(20.0));
+    }
+
+    #[test]
+    fn processes_share_rapl_power_while_charging() {
+        let rapl = RaplPower {
+            package: Some(5.0),
+            dram: Some(1.0),
+            ..RaplPower::default()
+        };
+        assert_eq!(process_power(&summary(false), &rapl), Some(6.0));
+    }
+
+    #[test]
+    fn processes_share_nothing_while_charging_without_rapl() {
+        assert_eq!(process_power(&summary(false), &RaplPower::default()), None);
+    }
+
+    #[test]
+    fn appending_to_archive_skips_rows_already_at_its_end() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("2024-01-01.csv");
+        let first = ["2024-01-01 10:00:00,a", "2024-01-01 10:00:04,b"];
+        let second = ["2024-01-01 10:00:04,b", "2024-01-01 10:00:08,c"];
+
+        assert!(append_to_archive(&path, &first).unwrap());
+        assert!(append_to_archive(&path, &second).unwrap());
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                CSV_HEADER,
+                "2024-01-01 10:00:00,a",
+                "2024-01-01 10:00:04,b",
+                "2024-01-01 10:00:08,c",
+            ]
+        );
+    }
+
+    #[test]
+    fn appending_drops_incomplete_row_at_end_of_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("2024-01-01.csv");
+        fs::write(
+            &path,
+            format!("{}\n2024-01-01 10:00:00,a\n2024-01-01 10:0", CSV_HEADER),
+        )
+        .unwrap();
+
+        assert!(append_to_archive(&path, &["2024-01-01 10:00:04,b"]).unwrap());
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            content,
+            format!(
+                "{}\n2024-01-01 10:00:00,a\n2024-01-01 10:00:04,b\n",
+                CSV_HEADER
+            )
+        );
+    }
+
+    #[test]
+    fn appending_leaves_archive_with_old_header_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("2024-01-01.csv");
+        let old = "Time,Status,Capacity(%),Power(W)\n2024-01-01 09:00:00,Full,100,0.00\n";
+        fs::write(&path, old).unwrap();
+
+        assert!(!append_to_archive(&path, &["2024-01-01 10:00:00,a"]).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), old);
+    }
+}
@@ -1,87 +1,516 @@
 use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::error::Error;
-use std::fs::{self, File};
+use std::fs::{self, DirBuilder, File, Permissions};
+use std::io::{self, Read};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone)]
+use crate::config::Config;
+
+#[derive(Debug, Clone, Default)]
 pub struct BatteryRecord {
     pub time: DateTime<Local>,
     pub status: String,
     pub capacity: f64,
     pub power: f64,
+    pub power_source: Option<String>,
+    pub voltage: Option<f64>,
+    pub current: Option<f64>,
+    pub energy_now: Option<f64>,
+    pub energy_full: Option<f64>,
+    pub charge_now: Option<f64>,
+    pub charge_full: Option<f64>,
+    pub battery: Option<String>,
+    pub adapter: Option<String>,
+    pub adapter_max_voltage: Option<f64>,
+    /// Kernel boot ID at the time of the sample; a change means a reboot.
+    pub boot_id: Option<String>,
+    /// Battery temperature in °C, when enabled and reported.
+    pub temperature: Option<f64>,
+    /// Temperature of the configured CPU sensor in °C.
+    pub cpu_temperature: Option<f64>,
+    /// Power of the RAPL domains (W), logged on request.
+    pub rapl_package: Option<f64>,
+    pub rapl_core: Option<f64>,
+    pub rapl_uncore: Option<f64>,
+    pub rapl_dram: Option<f64>,
+    /// Display brightness (% of maximum), average CPU frequency (MHz) and
+    /// 1-minute load average, logged on request.
+    pub brightness: Option<f64>,
+    pub cpu_freq_mhz: Option<f64>,
+    pub load: Option<f64>,
+}
+
+impl BatteryRecord {
+    /// Whether external power was connected, if the system reports it.
+    pub fn adapter_online(&self) -> Option<bool> {
+        self.adapter.as_deref().map(|a| a != "Offline")
+    }
+
+    /// Power of the CPU package plus DRAM, which RAPL measures whether or
+    /// not the battery is in use.
+    pub fn soc_power(&self) -> Option<f64> {
+        Some(self.rapl_package? + self.rapl_dram.unwrap_or(0.0))
+    }
+}
+
+/// Event rows the daemon writes between samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// The daemon started a new logging session.
+    Start,
+    Suspend,
+    Resume,
+}
+
+impl EventKind {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "start" => Some(EventKind::Start),
+            "suspend" => Some(EventKind::Suspend),
+            "resume" => Some(EventKind::Resume),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogEvent {
+    pub time: DateTime<Local>,
+    pub kind: EventKind,
+    /// Exact time spent suspended, set on `Resume` events.
+    pub suspended_secs: Option<i64>,
+}
+
+/// Samples and events read from one CSV file.
+#[derive(Debug, Default)]
+pub struct LogData {
+    pub records: Vec<BatteryRecord>,
+    pub events: Vec<LogEvent>,
+    /// Data rows read, including ones that could not be parsed.
+    pub rows: usize,
+    /// The file held fewer rows than were asked to be skipped, i.e. the
+    /// daemon moved them to an archive since the previous read.
+    pub shrunk: bool,
 }
 
-#[derive(Debug, Deserialize)]
+impl LogData {
+    pub fn extend(&mut self, other: LogData) {
+        self.records.extend(other.records);
+        self.events.extend(other.events);
+        self.rows += other.rows;
+    }
+}
+
+/// One log row, as read from CSV or received as JSON from the daemon's
+/// socket. JSON uses the snake_case field names, CSV the column headers.
+#[derive(Debug, Serialize, Deserialize)]
 struct CsvRecord {
-    #[serde(rename = "Time")]
+    #[serde(alias = "Time")]
     time: String,
-    #[serde(rename = "Status")]
+    #[serde(alias = "Status")]
     status: String,
-    #[serde(rename = "Capacity(%)")]
-    capacity: f64,
-    #[serde(rename = "Power(W)")]
-    power: f64,
+    #[serde(alias = "Capacity(%)")]
+    capacity: Option<f64>,
+    #[serde(alias = "Power(W)")]
+    power: Option<f64>,
+    #[serde(alias = "PowerSource", default)]
+    power_source: Option<String>,
+    #[serde(alias = "Voltage(V)", default)]
+    voltage: Option<f64>,
+    #[serde(alias = "Current(A)", default)]
+    current: Option<f64>,
+    #[serde(alias = "EnergyNow(Wh)", default)]
+    energy_now: Option<f64>,
+    #[serde(alias = "EnergyFull(Wh)", default)]
+    energy_full: Option<f64>,
+    #[serde(alias = "ChargeNow(Ah)", default)]
+    charge_now: Option<f64>,
+    #[serde(alias = "ChargeFull(Ah)", default)]
+    charge_full: Option<f64>,
+    #[serde(alias = "Battery", default)]
+    battery: Option<String>,
+    #[serde(alias = "Adapter", default)]
+    adapter: Option<String>,
+    #[serde(alias = "AdapterMax(V)", default)]
+    adapter_max_voltage: Option<f64>,
+    #[serde(alias = "Event", default)]
+    event: Option<String>,
+    #[serde(alias = "Suspended(s)", default)]
+    suspended_secs: Option<i64>,
+    #[serde(alias = "BootId", default)]
+    boot_id: Option<String>,
+    #[serde(alias = "Temp(C)", default)]
+    temperature: Option<f64>,
+    #[serde(alias = "CpuTemp(C)", default)]
+    cpu_temperature: Option<f64>,
+    #[serde(alias = "RaplPackage(W)", default)]
+    rapl_package: Option<f64>,
+    #[serde(alias = "RaplCore(W)", default)]
+    rapl_core: Option<f64>,
+    #[serde(alias = "RaplUncore(W)", default)]
+    rapl_uncore: Option<f64>,
+    #[serde(alias = "RaplDram(W)", default)]
+    rapl_dram: Option<f64>,
+    #[serde(alias = "Brightness(%)", default)]
+    brightness: Option<f64>,
+    #[serde(alias = "CpuFreq(MHz)", default)]
+    cpu_freq_mhz: Option<f64>,
+    #[serde(alias = "Load1", default)]
+    load: Option<f64>,
+}
+
+fn parse_time(time: &str) -> Result<DateTime<Local>, Box<dyn Error>> {
+    let naive = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S")?;
+    naive
+        .and_local_timezone(Local)
+        .earliest()
+        .ok_or_else(|| format!("Nonexistent local time: {}", time).into())
 }
 
 impl TryFrom<CsvRecord> for BatteryRecord {
-    type Error = chrono::ParseError;
+    type Error = Box<dyn Error>;
 
     fn try_from(csv: CsvRecord) -> Result<Self, Self::Error> {
-        let naive = NaiveDateTime::parse_from_str(&csv.time, "%Y-%m-%d %H:%M:%S")?;
-        let time = naive.and_local_timezone(Local).unwrap();
+        let time = parse_time(&csv.time)?;
 
         Ok(BatteryRecord {
             time,
             status: csv.status,
-            capacity: csv.capacity,
-            power: csv.power,
+            capacity: csv.capacity.ok_or("Missing capacity")?,
+            power: csv.power.ok_or("Missing power")?,
+            power_source: csv.power_source.filter(|s| !s.is_empty()),
+            voltage: csv.voltage,
+            current: csv.current,
+            energy_now: csv.energy_now,
+            energy_full: csv.energy_full,
+            charge_now: csv.charge_now,
+            charge_full: csv.charge_full,
+            battery: csv.battery.filter(|s| !s.is_empty()),
+            adapter: csv.adapter.filter(|s| !s.is_empty()),
+            adapter_max_voltage: csv.adapter_max_voltage,
+            boot_id: csv.boot_id.filter(|s| !s.is_empty()),
+            temperature: csv.temperature,
+            cpu_temperature: csv.cpu_temperature,
+            rapl_package: csv.rapl_package,
+            rapl_core: csv.rapl_core,
+            rapl_uncore: csv.rapl_uncore,
+            rapl_dram: csv.rapl_dram,
+            brightness: csv.brightness,
+            cpu_freq_mhz: csv.cpu_freq_mhz,
+            load: csv.load,
         })
     }
 }
 
-pub fn parse_csv<P: AsRef<Path>>(path: P) -> Result<Vec<BatteryRecord>, Box<dyn Error>> {
-    let file = File::open(path)?;
-    let mut reader = csv::Reader::from_reader(file);
-    let mut records = Vec::new();
+/// Rows written by older daemons have fewer columns than the current header,
+/// so the reader is flexible about record length.
+fn csv_reader<R: Read>(reader: R) -> csv::Reader<R> {
+    csv::ReaderBuilder::new().flexible(true).from_reader(reader)
+}
 
-    for result in reader.deserialize() {
-        let csv_record: CsvRecord = match result {
-            Ok(r) => r,
-            Err(_) => continue,
-        };
-        if let Ok(record) = BatteryRecord::try_from(csv_record) {
-            records.push(record);
+/// Sorts one CSV row into a sample or an event; unparseable rows are skipped.
+fn push_row(data: &mut LogData, csv_record: CsvRecord) {
+    if let Some(kind) = csv_record.event.as_deref().and_then(EventKind::parse) {
+        if let Ok(time) = parse_time(&csv_record.time) {
+            data.events.push(LogEvent {
+                time,
+                kind,
+                suspended_secs: csv_record.suspended_secs,
+            });
         }
+    } else if let Ok(record) = BatteryRecord::try_from(csv_record) {
+        data.records.push(record);
     }
+}
 
-    Ok(records)
+pub fn parse_csv<P: AsRef<Path>>(path: P) -> Result<LogData, Box<dyn Error>> {
+    parse_csv_from_line(path, 0)
 }
 
 pub fn parse_csv_from_line<P: AsRef<Path>>(
     path: P,
     skip_lines: usize,
-) -> Result<Vec<BatteryRecord>, Box<dyn Error>> {
+) -> Result<LogData, Box<dyn Error>> {
     let file = File::open(path)?;
-    let mut reader = csv::Reader::from_reader(file);
-    let mut records = Vec::new();
+    let mut reader = csv_reader(file);
+    let mut data = LogData::default();
 
-    for (i, result) in reader.deserialize().enumerate() {
-        if i < skip_lines {
+    let mut total = 0;
+    for result in reader.deserialize() {
+        total += 1;
+        if total <= skip_lines {
             continue;
         }
+        data.rows += 1;
 
-        let csv_record: CsvRecord = match result {
-            Ok(r) => r,
-            Err(_) => continue,
+        if let Ok(csv_record) = result {
+            push_row(&mut data, csv_record);
+        }
+    }
+    data.shrunk = total < skip_lines;
+
+    Ok(data)
+}
+
+/// Converts a row written under `header` to the JSON object the daemon
+/// serves on its socket.
+pub fn csv_row_to_json(header: &str, row: &str) -> Option<String> {
+    let content = format!("{}\n{}\n", header, row);
+    let csv_record: CsvRecord = csv_reader(content.as_bytes()).deserialize().next()?.ok()?;
+    serde_json::to_string(&csv_record).ok()
+}
+
+/// Adds one JSON row received from the daemon's socket.
+pub fn push_json_row(data: &mut LogData, line: &str) {
+    data.rows += 1;
+    if let Ok(csv_record) = serde_json::from_str::<CsvRecord>(line) {
+        push_row(data, csv_record);
+    }
+}
+
+/// Wear readings of one battery, logged by the daemon once a day and
+/// whenever they change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthRecord {
+    #[serde(rename = "Date")]
+    pub date: NaiveDate,
+    #[serde(rename = "Battery")]
+    pub battery: String,
+    #[serde(rename = "EnergyFull(Wh)")]
+    pub energy_full: Option<f64>,
+    #[serde(rename = "EnergyFullDesign(Wh)")]
+    pub energy_full_design: Option<f64>,
+    #[serde(rename = "ChargeFull(Ah)")]
+    pub charge_full: Option<f64>,
+    #[serde(rename = "ChargeFullDesign(Ah)")]
+    pub charge_full_design: Option<f64>,
+    #[serde(rename = "CycleCount")]
+    pub cycle_count: Option<u32>,
+}
+
+impl HealthRecord {
+    /// Full-charge capacity and its unit, in energy when the battery reports
+    /// it and in charge otherwise.
+    pub fn full_capacity(&self) -> Option<(f64, &'static str)> {
+        self.energy_full
+            .map(|full| (full, "Wh"))
+            .or_else(|| self.charge_full.map(|full| (full, "Ah")))
+    }
+
+    /// Full-charge capacity as a percentage of the design capacity.
+    pub fn health_percent(&self) -> Option<f64> {
+        let (full, design) = match (self.energy_full, self.energy_full_design) {
+            (Some(full), Some(design)) => (full, design),
+            _ => (self.charge_full?, self.charge_full_design?),
         };
+        (design > 0.0).then(|| full / design * 100.0)
+    }
 
-        if let Ok(record) = BatteryRecord::try_from(csv_record) {
-            records.push(record);
-        }
+    /// Whether the readings match `other`, whatever day either was taken.
+    pub fn same_readings(&self, other: &HealthRecord) -> bool {
+        HealthRecord {
+            date: other.date,
+            ..self.clone()
+        } == *other
+    }
+}
+
+pub fn get_health_log_path(config: &Config) -> PathBuf {
+    config.data_dir.join("health.csv")
+}
+
+/// Reads the health log in the order it was written; a missing file reads
+/// as empty and unparseable rows are skipped.
+pub fn read_health_log(path: &Path) -> Vec<HealthRecord> {
+    let Ok(file) = File::open(path) else {
+        return Vec::new();
+    };
+    csv_reader(file).deserialize().flatten().collect()
+}
+
+/// One of the processes that used the most energy in a window, as logged to
+/// the daily process log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessRecord {
+    /// End of the window.
+    #[serde(rename = "Time")]
+    pub time: String,
+    #[serde(rename = "Window(s)")]
+    pub window_secs: f64,
+    #[serde(rename = "Pid")]
+    pub pid: u32,
+    #[serde(rename = "Command")]
+    pub command: String,
+    #[serde(rename = "CpuTime(s)")]
+    pub cpu_secs: f64,
+    /// Share of the measured energy, in proportion to CPU time.
+    #[serde(rename = "Energy(J)")]
+    pub energy_joules: f64,
+}
+
+impl ProcessRecord {
+    pub fn parsed_time(&self) -> Option<DateTime<Local>> {
+        parse_time(&self.time).ok()
+    }
+}
+
+pub fn get_process_log_path(config: &Config, date: NaiveDate) -> PathBuf {
+    config
+        .data_dir
+        .join(format!("processes-{}.csv", date.format("%Y-%m-%d")))
+}
+
+/// Reads a process log; a missing file reads as empty and unparseable rows
+/// are skipped.
+pub fn read_process_log(path: &Path) -> Vec<ProcessRecord> {
+    let Ok(file) = File::open(path) else {
+        return Vec::new();
+    };
+    csv_reader(file).deserialize().flatten().collect()
+}
+
+pub fn battery_names(records: &[BatteryRecord]) -> Vec<String> {
+    let mut names: Vec<String> = records.iter().filter_map(|r| r.battery.clone()).collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Samples in a row a battery's last row is carried into when it is missing.
+const MAX_CARRIED_SAMPLES: usize = 3;
+
+/// Merges the per-battery rows logged for each sample into one record with
+/// summed power, the hottest battery's temperature and a capacity weighted
+/// by each battery's full energy (or full charge when energy is not
+/// reported).
+///
+/// A battery whose row is missing from a sample, e.g. because reading it
+/// failed once, is counted with its last logged row for up to
+/// `MAX_CARRIED_SAMPLES` samples so that the combined values do not jump.
+/// Rows are never carried across a daemon start or a reboot, and a battery
+/// missing for longer counts as removed.
+pub fn combine_batteries(records: &[BatteryRecord], events: &[LogEvent]) -> Vec<BatteryRecord> {
+    let mut starts: Vec<DateTime<Local>> = events
+        .iter()
+        .filter(|event| event.kind == EventKind::Start)
+        .map(|event| event.time)
+        .collect();
+    starts.sort();
+    let mut starts = starts.into_iter().peekable();
+
+    // Last row of each battery and the number of samples it was carried into.
+    let mut last_rows: BTreeMap<&str, (&BatteryRecord, usize)> = BTreeMap::new();
+    records
+        .chunk_by(|a, b| a.time == b.time)
+        .map(|group| {
+            let time = group[0].time;
+            let mut restarted = false;
+            while starts.next_if(|start| *start <= time).is_some() {
+                restarted = true;
+            }
+            if restarted {
+                last_rows.clear();
+            }
+            last_rows.retain(|_, (r, _)| r.boot_id == group[0].boot_id);
+
+            // A row logged twice for the same battery counts once.
+            let mut rows: Vec<BatteryRecord> = Vec::with_capacity(group.len());
+            for r in group {
+                match rows.iter_mut().find(|row| row.battery == r.battery) {
+                    Some(row) => *row = r.clone(),
+                    None => rows.push(r.clone()),
+                }
+            }
+
+            last_rows.retain(|_, (_, carried)| *carried < MAX_CARRIED_SAMPLES);
+            for (name, (r, carried)) in last_rows.iter_mut() {
+                if !group
+                    .iter()
+                    .any(|row| row.battery.as_deref() == Some(*name))
+                {
+                    *carried += 1;
+                    rows.push(BatteryRecord {
+                        time,
+                        ..(*r).clone()
+                    });
+                }
+            }
+            for r in group {
+                if let Some(name) = r.battery.as_deref() {
+                    last_rows.insert(name, (r, 0));
+                }
+            }
+
+            combine_sample(&rows)
+        })
+        .collect()
+}
+
+fn combine_sample(group: &[BatteryRecord]) -> BatteryRecord {
+    if group.len() == 1 {
+        return group[0].clone();
     }
 
-    Ok(records)
+    let weights: Vec<f64> = if group.iter().all(|r| r.energy_full.is_some()) {
+        group.iter().filter_map(|r| r.energy_full).collect()
+    } else if group.iter().all(|r| r.charge_full.is_some()) {
+        group.iter().filter_map(|r| r.charge_full).collect()
+    } else {
+        vec![1.0; group.len()]
+    };
+    let total_weight: f64 = weights.iter().sum();
+    let capacity = if total_weight > 0.0 {
+        group
+            .iter()
+            .zip(&weights)
+            .map(|(r, w)| r.capacity * w)
+            .sum::<f64>()
+            / total_weight
+    } else {
+        group.iter().map(|r| r.capacity).sum::<f64>() / group.len() as f64
+    };
+
+    let status = ["Discharging", "Charging"]
+        .into_iter()
+        .find(|s| group.iter().any(|r| r.status == *s))
+        .map(str::to_string)
+        .unwrap_or_else(|| group[0].status.clone());
+
+    let sum =
+        |field: fn(&BatteryRecord) -> Option<f64>| -> Option<f64> { group.iter().map(field).sum() };
+
+    BatteryRecord {
+        time: group[0].time,
+        status,
+        capacity,
+        power: group.iter().map(|r| r.power).sum(),
+        power_source: group
+            .iter()
+            .find_map(|r| r.power_source.clone().filter(|s| s != "power_now"))
+            .or_else(|| group[0].power_source.clone()),
+        voltage: None,
+        current: None,
+        energy_now: sum(|r| r.energy_now),
+        energy_full: sum(|r| r.energy_full),
+        charge_now: sum(|r| r.charge_now),
+        charge_full: sum(|r| r.charge_full),
+        battery: None,
+        adapter: group[0].adapter.clone(),
+        adapter_max_voltage: group[0].adapter_max_voltage,
+        boot_id: group[0].boot_id.clone(),
+        temperature: group.iter().filter_map(|r| r.temperature).reduce(f64::max),
+        cpu_temperature: group[0].cpu_temperature,
+        rapl_package: group[0].rapl_package,
+        rapl_core: group[0].rapl_core,
+        rapl_uncore: group[0].rapl_uncore,
+        rapl_dram: group[0].rapl_dram,
+        brightness: group[0].brightness,
+        cpu_freq_mhz: group[0].cpu_freq_mhz,
+        load: group[0].load,
+    }
 }
 
 pub fn get_data_dir() -> PathBuf {
@@ -94,49 +523,101 @@ pub fn get_data_dir() -> PathBuf {
     base.join("watt-monitor")
 }
 
+/// Directory for the live log, IPC socket and PID file, none of which need
+/// to outlive a reboot.
+///
+/// This is `/run/watt-monitor` when the OpenRC system service prepared it for
+/// the current user, otherwise `$XDG_RUNTIME_DIR/watt-monitor`. Without a
+/// runtime directory it falls back to a per-user directory under the system
+/// temp directory, which `create_runtime_dir` checks before use.
+pub fn get_runtime_dir() -> PathBuf {
+    let system_dir = Path::new("/run/watt-monitor");
+    if fs::symlink_metadata(system_dir).is_ok_and(|m| m.is_dir() && m.uid() == current_uid()) {
+        return system_dir.to_path_buf();
+    }
+
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(runtime_dir) if !runtime_dir.is_empty() => {
+            PathBuf::from(runtime_dir).join("watt-monitor")
+        }
+        _ => std::env::temp_dir().join(format!("watt-monitor-{}", current_uid())),
+    }
+}
+
+pub fn current_uid() -> u32 {
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    unsafe { libc::geteuid() }
+}
+
+/// Creates `path` readable only by the current user, or checks that an
+/// existing one is a real directory owned by them and tightens its mode.
+///
+/// The fallback location is in a world-writable directory, so another user
+/// may have created it first to read or tamper with the log.
+pub fn create_runtime_dir(path: &Path) -> io::Result<()> {
+    match DirBuilder::new().recursive(true).mode(0o700).create(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() || metadata.uid() != current_uid() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{:?} is not a directory owned by the current user", path),
+        ));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        fs::set_permissions(path, Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
 pub fn get_today_log_path() -> PathBuf {
-    PathBuf::from("/tmp/battery_watt_history.csv")
+    get_runtime_dir().join("battery_watt_history.csv")
 }
 
-pub fn get_archive_path_for_date(date: NaiveDate) -> PathBuf {
-    get_data_dir().join(format!("{}.csv", date.format("%Y-%m-%d")))
+pub fn get_archive_path_for_date(config: &Config, date: NaiveDate) -> PathBuf {
+    config
+        .data_dir
+        .join(format!("{}.csv", date.format("%Y-%m-%d")))
 }
 
-pub fn get_csv_path_for_date(date: NaiveDate) -> PathBuf {
+pub fn get_csv_path_for_date(config: &Config, date: NaiveDate) -> PathBuf {
     let today = Local::now().date_naive();
     if date == today {
-        get_today_log_path()
+        config.live_log.clone()
     } else {
-        get_archive_path_for_date(date)
+        get_archive_path_for_date(config, date)
     }
 }
 
-pub fn list_available_dates() -> Vec<NaiveDate> {
-    let data_dir = get_data_dir();
+pub fn list_available_dates(config: &Config) -> Vec<NaiveDate> {
+    let data_dir = &config.data_dir;
     let mut dates = Vec::new();
     let today = Local::now().date_naive();
 
-    if get_today_log_path().exists() {
+    if config.live_log.exists() {
         dates.push(today);
     }
 
-    if let Ok(entries) = fs::read_dir(&data_dir) {
+    // Checkpoints archive today's rows too, which stay readable after a
+    // reboot has cleared the live log.
+    if let Ok(entries) = fs::read_dir(data_dir) {
         for entry in entries.flatten() {
             let path = entry.path();
-            if path.extension().map(|e| e == "csv").unwrap_or(false) {
-                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
-                    if let Ok(date) = NaiveDate::parse_from_str(stem, "%Y-%m-%d") {
-                        // Don't duplicate today
-                        if date != today {
-                            dates.push(date);
-                        }
-                    }
-                }
+            if path.extension().map(|e| e == "csv").unwrap_or(false)
+                && let Some(stem) = path.file_stem().and_then(|s| s.to_str())
+                && let Ok(date) = NaiveDate::parse_from_str(stem, "%Y-%m-%d")
+            {
+                dates.push(date);
             }
         }
     }
 
     dates.sort_by(|a, b| b.cmp(a));
+    dates.dedup();
     dates
 }
 
@@ -148,3 +629,188 @@ pub fn parse_date_arg(arg: &str) -> Option<NaiveDate> {
         _ => NaiveDate::parse_from_str(arg, "%Y-%m-%d").ok(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(battery: &str, capacity: f64, power: f64) -> BatteryRecord {
+        BatteryRecord {
+            time: parse_time("2024-01-01 10:00:00").unwrap(),
+            status: "Discharging".to_string(),
+            capacity,
+            power,
+            battery: Some(battery.to_string()),
+            ..BatteryRecord::default()
+        }
+    }
+
+    #[test]
+    fn combined_capacity_is_weighted_by_full_energy() {
+        let records = [
+            BatteryRecord {
+                energy_full: Some(20.0),
+                ..sample("BAT0", 100.0, 2.0)
+            },
+            BatteryRecord {
+                energy_full: Some(60.0),
+                ..sample("BAT1", 20.0, 3.0)
+            },
+        ];
+
+        let combined = combine_batteries(&records, &[]);
+        assert_eq!(combined.len(), 1);
+        assert_eq!(combined[0].capacity, 40.0);
+        assert_eq!(combined[0].power, 5.0);
+        assert_eq!(combined[0].energy_full, Some(80.0));
+        assert_eq!(combined[0].battery, None);
+    }
+
+    #[test]
+    fn combined_capacity_falls_back_to_full_charge() {
+        let records = [
+            BatteryRecord {
+                energy_full: Some(20.0),
+                charge_full: Some(1.0),
+                ..sample("BAT0", 100.0, 2.0)
+            },
+            BatteryRecord {
+                charge_full: Some(3.0),
+                ..sample("BAT1", 20.0, 3.0)
+            },
+        ];
+
+        let combined = combine_batteries(&records, &[]);
+        assert_eq!(combined[0].capacity, 40.0);
+        assert_eq!(combined[0].energy_full, None);
+    }
+
+    #[test]
+    fn combined_capacity_is_plain_average_without_full_readings() {
+        let records = [
+            BatteryRecord {
+                energy_full: Some(20.0),
+                ..sample("BAT0", 100.0, 2.0)
+            },
+            sample("BAT1", 20.0, 3.0),
+        ];
+
+        assert_eq!(combine_batteries(&records, &[])[0].capacity, 60.0);
+    }
+
+    #[test]
+    fn today_is_listed_once_from_its_archive_or_the_live_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config {
+            data_dir: dir.path().to_path_buf(),
+            live_log: dir.path().join("live.csv"),
+            daemon: Default::default(),
+            tui: Default::default(),
+        };
+        let today = Local::now().date_naive();
+        fs::write(get_archive_path_for_date(&config, today), "").unwrap();
+        assert_eq!(list_available_dates(&config), [today]);
+
+        fs::write(&config.live_log, "").unwrap();
+        assert_eq!(list_available_dates(&config), [today]);
+    }
+
+    #[test]
+    fn missing_battery_row_is_carried_forward() {
+        let later = |record: BatteryRecord| BatteryRecord {
+            time: parse_time("2024-01-01 10:00:04").unwrap(),
+            ..record
+        };
+        let records = [
+            sample("BAT0", 80.0, 2.0),
+            sample("BAT1", 40.0, 3.0),
+            // Reading BAT1 failed for this sample.
+            later(sample("BAT0", 79.0, 2.0)),
+        ];
+
+        let combined = combine_batteries(&records, &[]);
+        assert_eq!(combined.len(), 2);
+        assert_eq!(combined[1].capacity, 59.5);
+        assert_eq!(combined[1].power, 5.0);
+        assert_eq!(combined[1].time, records[2].time);
+    }
+
+    fn at(seconds: u32, record: BatteryRecord) -> BatteryRecord {
+        BatteryRecord {
+            time: parse_time(&format!("2024-01-01 10:00:{:02}", seconds)).unwrap(),
+            ..record
+        }
+    }
+
+    #[test]
+    fn removed_battery_is_carried_only_for_a_few_samples() {
+        let mut records = vec![sample("BAT0", 80.0, 2.0), sample("BAT1", 40.0, 3.0)];
+        records.extend((1..=5).map(|i| at(i * 4, sample("BAT0", 80.0, 2.0))));
+
+        let powers: Vec<f64> = combine_batteries(&records, &[])
+            .iter()
+            .map(|r| r.power)
+            .collect();
+        assert_eq!(powers, [5.0, 5.0, 5.0, 5.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn rows_are_not_carried_across_a_daemon_start() {
+        let records = [
+            sample("BAT0", 80.0, 2.0),
+            sample("BAT1", 40.0, 3.0),
+            at(8, sample("BAT0", 80.0, 2.0)),
+        ];
+        let start = LogEvent {
+            time: parse_time("2024-01-01 10:00:06").unwrap(),
+            kind: EventKind::Start,
+            suspended_secs: None,
+        };
+
+        let combined = combine_batteries(&records, &[start]);
+        assert_eq!(combined[1].power, 2.0);
+        assert_eq!(combined[1].capacity, 80.0);
+    }
+
+    #[test]
+    fn rows_are_not_carried_across_a_reboot() {
+        let booted = |boot_id: &str, record: BatteryRecord| BatteryRecord {
+            boot_id: Some(boot_id.to_string()),
+            ..record
+        };
+        let records = [
+            booted("a", sample("BAT0", 80.0, 2.0)),
+            booted("a", sample("BAT1", 40.0, 3.0)),
+            at(4, booted("b", sample("BAT0", 80.0, 2.0))),
+        ];
+
+        assert_eq!(combine_batteries(&records, &[])[1].power, 2.0);
+    }
+
+    #[test]
+    fn duplicate_row_of_a_battery_counts_once() {
+        let records = [
+            sample("BAT0", 80.0, 2.0),
+            sample("BAT0", 80.0, 2.0),
+            sample("BAT1", 40.0, 3.0),
+        ];
+
+        let combined = combine_batteries(&records, &[]);
+        assert_eq!(combined.len(), 1);
+        assert_eq!(combined[0].power, 5.0);
+        assert_eq!(combined[0].capacity, 60.0);
+    }
+
+    #[test]
+    fn samples_taken_at_different_times_are_not_combined() {
+        let later = BatteryRecord {
+            time: parse_time("2024-01-01 10:00:04").unwrap(),
+            ..sample("BAT0", 99.0, 2.0)
+        };
+        let records = [sample("BAT0", 100.0, 2.0), later];
+
+        let combined = combine_batteries(&records, &[]);
+        assert_eq!(combined.len(), 2);
+        assert_eq!(combined[0].battery.as_deref(), Some("BAT0"));
+    }
+}
@@ -0,0 +1,135 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::daemon::LogBuffer;
+use crate::data::{LogData, get_runtime_dir, push_json_row};
+use crate::logging::warn;
+
+/// How long either side waits for the other to send its request or answer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A client sends one request per connection as a JSON line, e.g.
+/// `{"request":"last","count":10}`, and reads newline-delimited JSON rows
+/// back. The connection is closed after the answer, except for `subscribe`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    /// The rows of the most recent sample, one per battery.
+    Current,
+    /// The last `count` sample rows.
+    Last { count: usize },
+    /// Unflushed rows, then every new row until the connection closes.
+    Subscribe,
+}
+
+pub fn get_socket_path() -> PathBuf {
+    get_runtime_dir().join("daemon.sock")
+}
+
+/// Serves requests for the rows in `buffer` on `socket_path`.
+pub fn serve(socket_path: &Path, buffer: LogBuffer) -> io::Result<()> {
+    // A socket left by a daemon that did not shut down cleanly blocks bind.
+    match fs::remove_file(socket_path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let listener = UnixListener::bind(socket_path)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            // A client that is slow to send its request must not hold up
+            // the others.
+            let buffer = buffer.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle_client(stream, &buffer) {
+                    warn!("Failed to answer IPC request: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn handle_client(mut stream: UnixStream, buffer: &LogBuffer) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let rows = match serde_json::from_str::<Request>(&line) {
+        Ok(Request::Current) => buffer.current_sample(),
+        Ok(Request::Last { count }) => buffer.last_samples(count),
+        Ok(Request::Subscribe) => return buffer.subscribe(stream),
+        Err(e) => vec![serde_json::json!({ "error": e.to_string() }).to_string()],
+    };
+
+    for row in rows {
+        writeln!(stream, "{}", row)?;
+    }
+    Ok(())
+}
+
+/// Sends a single `current` or `last` request and returns the answer.
+pub fn request(socket_path: &Path, request: &Request) -> io::Result<LogData> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    writeln!(
+        stream,
+        "{}",
+        serde_json::to_string(request).expect("request is serializable")
+    )?;
+
+    let mut data = LogData::default();
+    for line in BufReader::new(stream).lines() {
+        push_json_row(&mut data, &line?);
+    }
+    Ok(data)
+}
+
+/// Rows streamed from the daemon, read on a background thread so that the
+/// TUI never blocks on the socket.
+pub struct Subscription {
+    rows: Receiver<String>,
+}
+
+impl Subscription {
+    pub fn connect(socket_path: &Path) -> io::Result<Self> {
+        let mut stream = UnixStream::connect(socket_path)?;
+        writeln!(
+            stream,
+            "{}",
+            serde_json::to_string(&Request::Subscribe).expect("request is serializable")
+        )?;
+
+        let (sender, rows) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Subscription { rows })
+    }
+
+    /// Adds the rows received since the last call to `data` and returns
+    /// whether the daemon is still connected.
+    pub fn poll(&self, data: &mut LogData) -> bool {
+        loop {
+            match self.rows.try_recv() {
+                Ok(line) => push_json_row(data, &line),
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::AsFd;
+use std::os::unix::fs::MetadataExt;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Messages of this level and more severe ones are written to stderr.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    /// Syslog priority understood by journald as a `<N>` line prefix.
+    fn priority(self) -> u8 {
+        match self {
+            LogLevel::Error => 3,
+            LogLevel::Warn => 4,
+            LogLevel::Info => 6,
+            LogLevel::Debug => 7,
+        }
+    }
+}
+
+struct Logger {
+    level: LogLevel,
+    journal: bool,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+impl Logger {
+    fn new(level: LogLevel) -> Self {
+        Logger {
+            level,
+            journal: stderr_is_journal(),
+        }
+    }
+}
+
+/// Sets the level for the rest of the process. Messages logged before this
+/// use the default level.
+pub fn init(level: LogLevel) {
+    let _ = LOGGER.set(Logger::new(level));
+}
+
+/// systemd exports the device and inode of the journal stream in
+/// `$JOURNAL_STREAM`; it only applies if stderr still is that stream.
+fn stderr_is_journal() -> bool {
+    let Ok(stream) = std::env::var("JOURNAL_STREAM") else {
+        return false;
+    };
+    let Some((dev, ino)) = stream.split_once(':') else {
+        return false;
+    };
+    let Ok(stderr) = io::stderr()
+        .as_fd()
+        .try_clone_to_owned()
+        .and_then(|fd| File::from(fd).metadata())
+    else {
+        return false;
+    };
+    dev.parse() == Ok(stderr.dev()) && ino.parse() == Ok(stderr.ino())
+}
+
+pub fn log(level: LogLevel, message: fmt::Arguments) {
+    let logger = LOGGER.get_or_init(|| Logger::new(LogLevel::default()));
+    if level > logger.level {
+        return;
+    }
+
+    let mut stderr = io::stderr().lock();
+    let _ = if logger.journal {
+        writeln!(stderr, "<{}>{}", level.priority(), message)
+    } else {
+        match level {
+            LogLevel::Error => writeln!(stderr, "Error: {}", message),
+            LogLevel::Warn => writeln!(stderr, "Warning: {}", message),
+            LogLevel::Info | LogLevel::Debug => writeln!(stderr, "{}", message),
+        }
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::logging::LogLevel::Error, format_args!($($arg)*))
+    };
+}
+
+// Named apart from the built-in `#[warn]` attribute, which a bare `warn`
+// would be ambiguous with, and exported as `warn` below.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::logging::LogLevel::Warn, format_args!($($arg)*))
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::logging::LogLevel::Info, format_args!($($arg)*))
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::logging::log($crate::logging::LogLevel::Debug, format_args!($($arg)*))
+    };
+}
+
+pub(crate) use {debug, error, info, warning as warn};
+
+/// Lets a recurring message through at most once per `interval`, counting
+/// the repeats it held back in between.
+pub struct RateLimit {
+    interval: Duration,
+    last: Option<Instant>,
+    suppressed: u64,
+}
+
+impl RateLimit {
+    pub fn new(interval: Duration) -> Self {
+        RateLimit {
+            interval,
+            last: None,
+            suppressed: 0,
+        }
+    }
+
+    /// Returns the number of messages suppressed since the last one that was
+    /// let through, or `None` if this one should be suppressed too.
+    pub fn allow(&mut self) -> Option<u64> {
+        if self.last.is_some_and(|last| last.elapsed() < self.interval) {
+            self.suppressed += 1;
+            return None;
+        }
+        self.last = Some(Instant::now());
+        Some(std::mem::take(&mut self.suppressed))
+    }
+
+    /// Starts over, e.g. once the condition being reported has cleared.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.suppressed = 0;
+    }
+}
@@ -1,9 +1,16 @@
 mod app;
+mod config;
 mod daemon;
 mod data;
+mod ipc;
+mod logging;
+mod notify;
+mod processes;
+mod rapl;
+mod sampling;
 mod ui;
 
-use std::{io, time::Duration};
+use std::{io, path::PathBuf, time::Duration};
 
 use chrono::{Local, NaiveDate};
 use clap::{Parser, Subcommand};
@@ -11,7 +18,12 @@ use crossterm::event::{self, Event, KeyCode, KeyEventKind};
 use ratatui::DefaultTerminal;
 
 use app::App;
-use data::{list_available_dates, parse_date_arg};
+use config::{Config, get_config_path};
+use data::{
+    HealthRecord, get_health_log_path, list_available_dates, parse_date_arg, read_health_log,
+};
+use logging::LogLevel;
+use sampling::SamplingMode;
 
 #[derive(Parser)]
 #[command(name = "watt-monitor")]
@@ -23,31 +35,145 @@ struct Cli {
 
     #[arg(short, long)]
     date: Option<String>,
+
+    /// Directory holding the daily CSV archives
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+
+    /// CSV file the daemon appends to during the current day
+    #[arg(long, global = true)]
+    live_log: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    Daemon,
+    Daemon {
+        /// Log only this power supply (e.g. BAT0, CMB0) instead of every battery
+        #[arg(short, long)]
+        battery: Option<String>,
+
+        /// Root of the sysfs tree to read power supplies from [default: /sys]
+        #[arg(long, env = "WATT_MONITOR_SYSFS")]
+        sysfs_root: Option<PathBuf>,
+
+        /// Seconds between samples [default: 4]
+        #[arg(short, long)]
+        interval: Option<u64>,
+
+        /// Sampling strategy [default: fixed]
+        #[arg(long, value_enum)]
+        sampling: Option<SamplingMode>,
+
+        /// Least severe messages to log [default: info]
+        #[arg(long, value_enum)]
+        log_level: Option<LogLevel>,
+
+        /// Report whether a daemon is running instead of starting one
+        #[arg(long, conflicts_with = "stop")]
+        status: bool,
+
+        /// Stop the running daemon instead of starting one
+        #[arg(long)]
+        stop: bool,
+    },
     List,
+    /// Show how battery capacity and cycle count changed over time
+    Health,
+    /// Inspect the configuration file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print the settings after applying the config file and CLI flags
+    Show,
 }
 
 fn main() -> io::Result<()> {
     let cli = Cli::parse();
 
+    let mut config = Config::load()?;
+    if let Some(data_dir) = cli.data_dir {
+        config.data_dir = data_dir;
+    }
+    if let Some(live_log) = cli.live_log {
+        config.live_log = live_log;
+    }
+
     match cli.command {
-        Some(Commands::Daemon) => daemon::run(),
+        Some(Commands::Daemon {
+            battery,
+            sysfs_root,
+            interval,
+            sampling,
+            log_level,
+            status,
+            stop,
+        }) => {
+            if battery.is_some() {
+                config.daemon.battery = battery;
+            }
+            if let Some(sysfs_root) = sysfs_root {
+                config.daemon.sysfs_root = sysfs_root;
+            }
+            if let Some(interval) = interval {
+                config.daemon.log_interval_secs = interval.max(1);
+            }
+            if let Some(sampling) = sampling {
+                config.daemon.sampling = sampling;
+            }
+            if let Some(log_level) = log_level {
+                config.daemon.log_level = log_level;
+            }
+            logging::init(config.daemon.log_level);
+
+            if status {
+                if !daemon::status(&config)? {
+                    // LSB exit code for "program is not running".
+                    std::process::exit(3);
+                }
+                Ok(())
+            } else if stop {
+                daemon::stop()
+            } else {
+                daemon::run(&config)
+            }
+        }
         Some(Commands::List) => {
-            print_available_dates();
+            print_available_dates(&config);
             Ok(())
         }
-        None => run_tui(cli.date),
+        Some(Commands::Health) => {
+            print_health(&config);
+            Ok(())
+        }
+        Some(Commands::Config {
+            action: ConfigAction::Show,
+        }) => {
+            print_config(&config);
+            Ok(())
+        }
+        None => run_tui(config, cli.date),
+    }
+}
+
+fn print_config(config: &Config) {
+    let path = get_config_path();
+    if path.exists() {
+        println!("# Loaded from {}", path.display());
+    } else {
+        println!("# {} not found, using defaults", path.display());
     }
+    print!("{}", config.to_toml());
 }
 
-fn print_available_dates() {
-    let dates = list_available_dates();
+fn print_available_dates(config: &Config) {
+    let dates = list_available_dates(config);
     if dates.is_empty() {
-        println!("No data files found in {:?}", data::get_data_dir());
+        println!("No data files found in {:?}", config.data_dir);
         println!("Start the daemon: watt-monitor daemon");
         println!("Or enable systemd service: systemctl --user enable --now watt-monitor.service");
     } else {
@@ -58,8 +184,74 @@ fn print_available_dates() {
     }
 }
 
-fn run_tui(date_arg: Option<String>) -> io::Result<()> {
-    let available_dates = list_available_dates();
+/// Prints the latest wear readings of each battery, then the last reading
+/// of every month.
+fn print_health(config: &Config) {
+    let path = get_health_log_path(config);
+    let records = read_health_log(&path);
+    if records.is_empty() {
+        println!("No health data found in {:?}", path);
+        println!("The daemon records it once a day while running.");
+        return;
+    }
+
+    let format_row = |record: &HealthRecord| {
+        let full = record
+            .full_capacity()
+            .map(|(full, unit)| format!("{:.2} {}", full, unit))
+            .unwrap_or_else(|| "N/A".to_string());
+        let health = record
+            .health_percent()
+            .map(|percent| format!("{:.1}%", percent))
+            .unwrap_or_else(|| "N/A".to_string());
+        let cycles = record
+            .cycle_count
+            .map(|count| count.to_string())
+            .unwrap_or_else(|| "N/A".to_string());
+        format!(
+            "{:<10} {:>12} {:>8} {:>8}",
+            record.battery, full, health, cycles
+        )
+    };
+    let print_header = |first_column: &str| {
+        println!(
+            "{:<10} {:<10} {:>12} {:>8} {:>8}",
+            first_column, "Battery", "Full", "Health", "Cycles"
+        );
+    };
+
+    print_header("Date");
+    let mut latest: Vec<&HealthRecord> = Vec::new();
+    for record in records.iter().rev() {
+        if !latest.iter().any(|r| r.battery == record.battery) {
+            latest.push(record);
+        }
+    }
+    latest.sort_by(|a, b| a.battery.cmp(&b.battery));
+    for record in latest {
+        println!("{} {}", record.date.format("%Y-%m-%d"), format_row(record));
+    }
+
+    println!();
+    print_header("Month");
+    let mut monthly: Vec<&HealthRecord> = Vec::new();
+    for record in &records {
+        let month = record.date.format("%Y-%m").to_string();
+        match monthly
+            .iter_mut()
+            .find(|r| r.battery == record.battery && r.date.format("%Y-%m").to_string() == month)
+        {
+            Some(entry) => *entry = record,
+            None => monthly.push(record),
+        }
+    }
+    for record in monthly {
+        println!("{:<10} {}", record.date.format("%Y-%m"), format_row(record));
+    }
+}
+
+fn run_tui(config: Config, date_arg: Option<String>) -> io::Result<()> {
+    let available_dates = list_available_dates(&config);
 
     let target_date: NaiveDate = if let Some(ref date_str) = date_arg {
         parse_date_arg(date_str).unwrap_or_else(|| {
@@ -79,7 +271,7 @@ fn run_tui(date_arg: Option<String>) -> io::Result<()> {
     };
 
     let mut terminal = ratatui::init();
-    let result = run(&mut terminal, target_date, available_dates);
+    let result = run(&mut terminal, config, target_date, available_dates);
     ratatui::restore();
 
     result
@@ -87,47 +279,71 @@ fn run_tui(date_arg: Option<String>) -> io::Result<()> {
 
 fn run(
     terminal: &mut DefaultTerminal,
+    config: Config,
     initial_date: NaiveDate,
     available_dates: Vec<NaiveDate>,
 ) -> io::Result<()> {
-    let mut app = App::new(initial_date, available_dates);
+    let mut app = App::new(config, initial_date, available_dates);
     let tick_rate = Duration::from_millis(500);
 
     loop {
         terminal.draw(|frame| ui::draw(frame, &app))?;
 
-        if event::poll(tick_rate)? {
-            if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
-                    if app.show_service_warning {
-                        app.dismiss_warning();
-                        continue;
-                    }
-
-                    if app.show_about {
-                        app.dismiss_about();
-                        continue;
-                    }
-
-                    match key.code {
-                        KeyCode::Char('q') | KeyCode::Esc => {
-                            app.should_quit = true;
-                        }
-                        KeyCode::Tab => {
-                            app.toggle_view_mode();
-                        }
-                        KeyCode::Left => {
-                            app.navigate_date(-1);
-                        }
-                        KeyCode::Right | KeyCode::Char('l') => {
-                            app.navigate_date(1);
-                        }
-                        KeyCode::Char('h') => {
-                            app.toggle_about();
-                        }
-                        _ => {}
-                    }
+        if event::poll(tick_rate)?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            if app.show_service_warning {
+                app.dismiss_warning();
+                continue;
+            }
+
+            if app.show_about {
+                app.dismiss_about();
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => {
+                    app.should_quit = true;
+                }
+                KeyCode::Tab => {
+                    app.toggle_view_mode();
+                }
+                KeyCode::Left => {
+                    app.navigate_date(-1);
+                }
+                KeyCode::Right | KeyCode::Char('l') => {
+                    app.navigate_date(1);
+                }
+                KeyCode::Char('b') => {
+                    app.cycle_battery();
+                }
+                KeyCode::Char('o') => {
+                    app.cycle_overlay();
+                }
+                KeyCode::Char('i') => {
+                    app.toggle_inspector();
+                }
+                KeyCode::Char('[') => {
+                    app.move_inspector(-1);
+                }
+                KeyCode::Char(']') => {
+                    app.move_inspector(1);
+                }
+                KeyCode::Char('p') => {
+                    app.toggle_processes();
+                }
+                KeyCode::Char('t') => {
+                    app.toggle_temperature();
+                }
+                KeyCode::Char('w') => {
+                    app.toggle_health();
+                }
+                KeyCode::Char('h') => {
+                    app.toggle_about();
                 }
+                _ => {}
             }
         }
 
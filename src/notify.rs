@@ -0,0 +1,87 @@
+use std::cell::RefCell;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+use crate::logging::{RateLimit, error, warn};
+
+/// How often failures to reach the service manager are reported.
+const ERROR_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Client for systemd's `sd_notify` protocol, spoken directly over the
+/// datagram socket systemd passes in `$NOTIFY_SOCKET`.
+pub struct Notifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+    watchdog_interval: Option<Duration>,
+    errors: RefCell<RateLimit>,
+}
+
+impl Notifier {
+    /// Returns `None` unless the daemon was started by a service manager that
+    /// expects notifications, e.g. a `Type=notify` unit.
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var("NOTIFY_SOCKET").ok()?;
+        let addr = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name),
+            None => SocketAddr::from_pathname(&path),
+        };
+        let addr = addr
+            .inspect_err(|e| error!("Invalid NOTIFY_SOCKET {:?}: {}", path, e))
+            .ok()?;
+        let socket = UnixDatagram::unbound()
+            .inspect_err(|e| error!("Failed to create notify socket: {}", e))
+            .ok()?;
+
+        Some(Notifier {
+            socket,
+            addr,
+            watchdog_interval: watchdog_interval(),
+            errors: RefCell::new(RateLimit::new(ERROR_INTERVAL)),
+        })
+    }
+
+    /// How often the watchdog must be pinged, if systemd enabled it.
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog_interval
+    }
+
+    /// Sends newline-separated `KEY=VALUE` assignments. Failures are logged
+    /// but never stop the daemon.
+    pub fn notify(&self, state: &str) {
+        if let Err(e) = self.socket.send_to_addr(state.as_bytes(), &self.addr)
+            && self.errors.borrow_mut().allow().is_some()
+        {
+            warn!("Failed to notify service manager: {}", e);
+        }
+    }
+
+    pub fn ready(&self, status: &str) {
+        self.notify(&format!("READY=1\nSTATUS={}", status));
+    }
+
+    pub fn status(&self, status: &str) {
+        self.notify(&format!("STATUS={}", status));
+    }
+
+    pub fn watchdog(&self) {
+        self.notify("WATCHDOG=1");
+    }
+
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+}
+
+/// Half of `$WATCHDOG_USEC`, as systemd recommends, when the watchdog is
+/// meant for this process.
+fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse() != Ok(std::process::id())
+    {
+        return None;
+    }
+
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
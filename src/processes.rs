@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// CPU time and energy attributed to one process over a window.
+#[derive(Debug, Clone)]
+pub struct ProcessUsage {
+    pub pid: u32,
+    pub command: String,
+    pub cpu_secs: f64,
+    pub energy_joules: f64,
+}
+
+/// A process is identified by its PID and start time, so that a reused PID
+/// is not mistaken for the process that had it before.
+type ProcessKey = (u32, u64);
+
+struct ProcessTimes {
+    command: String,
+    /// User plus system time, in clock ticks.
+    cpu_ticks: u64,
+    start_time: u64,
+}
+
+/// Parses `/proc/<pid>/stat`. The command name is in parentheses and may
+/// itself contain spaces and parentheses, so fields are counted from the
+/// last `)`.
+fn parse_stat(stat: &str) -> Option<ProcessTimes> {
+    let open = stat.find('(')?;
+    let close = stat.rfind(')')?;
+    let command = stat.get(open + 1..close)?.to_string();
+    // Fields after the command, starting with field 3 (state).
+    let fields: Vec<&str> = stat.get(close + 1..)?.split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    let start_time: u64 = fields.get(19)?.parse().ok()?;
+
+    Some(ProcessTimes {
+        command,
+        cpu_ticks: utime + stime,
+        start_time,
+    })
+}
+
+fn clock_ticks_per_sec() -> f64 {
+    // SAFETY: sysconf has no preconditions.
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks > 0 { ticks as f64 } else { 100.0 }
+}
+
+/// Shares the measured power among processes in proportion to the CPU time
+/// each used between samples, and sums it up over a reporting window.
+pub struct ProcessCollector {
+    proc_root: PathBuf,
+    ticks_per_sec: f64,
+    previous: Option<(Instant, HashMap<ProcessKey, u64>)>,
+    window: HashMap<ProcessKey, ProcessUsage>,
+    window_start: Instant,
+}
+
+impl ProcessCollector {
+    pub fn new(proc_root: &Path) -> Self {
+        ProcessCollector {
+            proc_root: proc_root.to_path_buf(),
+            ticks_per_sec: clock_ticks_per_sec(),
+            previous: None,
+            window: HashMap::new(),
+            window_start: Instant::now(),
+        }
+    }
+
+    fn read_processes(&self) -> HashMap<ProcessKey, ProcessTimes> {
+        fs::read_dir(&self.proc_root)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+                // Processes may exit between listing and reading.
+                let stat = fs::read_to_string(entry.path().join("stat")).ok()?;
+                let times = parse_stat(&stat)?;
+                Some(((pid, times.start_time), times))
+            })
+            .collect()
+    }
+
+    /// Attributes `watts`, drawn since the previous call, to the processes
+    /// that used CPU time in between. Processes that started meanwhile are
+    /// charged for all of their CPU time. When the power drawn is unknown,
+    /// only the CPU times are recorded for the next call.
+    pub fn sample(&mut self, watts: Option<f64>) {
+        let now = Instant::now();
+        let processes = self.read_processes();
+        let ticks = processes
+            .iter()
+            .map(|(key, times)| (*key, times.cpu_ticks))
+            .collect();
+
+        let Some((previous_at, previous)) = self.previous.replace((now, ticks)) else {
+            return;
+        };
+        let Some(watts) = watts else {
+            return;
+        };
+        let deltas: Vec<(ProcessKey, &ProcessTimes, u64)> = processes
+            .iter()
+            .map(|(key, times)| {
+                let before = previous.get(key).copied().unwrap_or(0);
+                (*key, times, times.cpu_ticks.saturating_sub(before))
+            })
+            .filter(|(_, _, delta)| *delta > 0)
+            .collect();
+        let total_ticks: u64 = deltas.iter().map(|(_, _, delta)| delta).sum();
+        if total_ticks == 0 {
+            return;
+        }
+
+        let energy = watts.max(0.0) * now.duration_since(previous_at).as_secs_f64();
+        for (key, times, delta) in deltas {
+            let usage = self.window.entry(key).or_insert_with(|| ProcessUsage {
+                pid: key.0,
+                command: times.command.clone(),
+                cpu_secs: 0.0,
+                energy_joules: 0.0,
+            });
+            usage.cpu_secs += delta as f64 / self.ticks_per_sec;
+            usage.energy_joules += energy * delta as f64 / total_ticks as f64;
+        }
+    }
+
+    /// Once `interval` has passed since the window started, starts a new one
+    /// and returns the length of the old one with its `count` processes that
+    /// used the most energy.
+    pub fn take_top(
+        &mut self,
+        interval: Duration,
+        count: usize,
+    ) -> Option<(Duration, Vec<ProcessUsage>)> {
+        let elapsed = self.window_start.elapsed();
+        if elapsed < interval {
+            return None;
+        }
+        self.window_start = Instant::now();
+
+        let mut usage: Vec<ProcessUsage> = self.window.drain().map(|(_, usage)| usage).collect();
+        usage.sort_by(|a, b| b.energy_joules.total_cmp(&a.energy_joules));
+        usage.truncate(count);
+        Some((elapsed, usage))
+    }
+}
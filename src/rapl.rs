@@ -0,0 +1,152 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Average power drawn by each kind of RAPL domain since the previous read,
+/// summed over CPU packages, in watts.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RaplPower {
+    pub package: Option<f64>,
+    pub core: Option<f64>,
+    pub uncore: Option<f64>,
+    pub dram: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DomainKind {
+    Package,
+    Core,
+    Uncore,
+    Dram,
+}
+
+impl DomainKind {
+    /// Maps the `name` the kernel gives a zone, e.g. `package-0` or `dram`.
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "core" => Some(DomainKind::Core),
+            "uncore" => Some(DomainKind::Uncore),
+            "dram" => Some(DomainKind::Dram),
+            _ if name.starts_with("package-") => Some(DomainKind::Package),
+            _ => None,
+        }
+    }
+}
+
+struct Domain {
+    kind: DomainKind,
+    energy_path: PathBuf,
+    /// Value at which `energy_uj` wraps around to zero.
+    max_energy_uj: u64,
+    last: Option<(Instant, u64)>,
+}
+
+fn read_u64(path: &Path) -> io::Result<u64> {
+    fs::read_to_string(path)?
+        .trim()
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Derives CPU package, core, uncore and DRAM power from the RAPL energy
+/// counters in powercap, which keep counting on AC when the battery reports
+/// no power at all.
+pub struct RaplCollector {
+    domains: Vec<Domain>,
+}
+
+impl RaplCollector {
+    /// Finds the RAPL zones under `<sysfs_root>/class/powercap` and takes a
+    /// first reading of each.
+    ///
+    /// Fails when there are none, or when the counters cannot be read; since
+    /// Linux 5.10 `energy_uj` is only readable by root by default.
+    pub fn discover(sysfs_root: &Path) -> io::Result<Self> {
+        let mut zones: Vec<PathBuf> = fs::read_dir(sysfs_root.join("class/powercap"))?
+            .flatten()
+            .map(|entry| entry.path())
+            // `intel-rapl` itself is the control type, and `intel-rapl-mmio`
+            // zones duplicate the package counter on some platforms.
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("intel-rapl:"))
+            })
+            .collect();
+        zones.sort();
+
+        let mut domains = Vec::new();
+        for zone in zones {
+            let Some(kind) = fs::read_to_string(zone.join("name"))
+                .ok()
+                .and_then(|name| DomainKind::from_name(name.trim()))
+            else {
+                continue;
+            };
+            let energy_path = zone.join("energy_uj");
+            let energy = read_u64(&energy_path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{:?}: {}", energy_path, e)))?;
+            domains.push(Domain {
+                kind,
+                energy_path,
+                max_energy_uj: read_u64(&zone.join("max_energy_range_uj")).unwrap_or(u64::MAX),
+                last: Some((Instant::now(), energy)),
+            });
+        }
+
+        if domains.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No RAPL zones found in powercap",
+            ));
+        }
+        Ok(RaplCollector { domains })
+    }
+
+    /// Returns the power drawn since the previous call. Domains whose counter
+    /// cannot be read, or that have no previous reading, are left out.
+    pub fn read(&mut self) -> RaplPower {
+        let mut power = RaplPower::default();
+        for domain in &mut self.domains {
+            let Ok(energy) = read_u64(&domain.energy_path) else {
+                domain.last = None;
+                continue;
+            };
+            let now = Instant::now();
+            let previous = domain.last.replace((now, energy));
+
+            let Some((at, last_energy)) = previous else {
+                continue;
+            };
+            let secs = now.duration_since(at).as_secs_f64();
+            if secs <= 0.0 {
+                continue;
+            }
+            let delta = if energy >= last_energy {
+                energy - last_energy
+            } else {
+                // The counter wrapped around since the previous read.
+                domain.max_energy_uj.saturating_sub(last_energy) + energy
+            };
+            let watts = delta as f64 / 1_000_000.0 / secs;
+
+            let total = match domain.kind {
+                DomainKind::Package => &mut power.package,
+                DomainKind::Core => &mut power.core,
+                DomainKind::Uncore => &mut power.uncore,
+                DomainKind::Dram => &mut power.dram,
+            };
+            *total = Some(total.unwrap_or(0.0) + watts);
+        }
+        power
+    }
+
+    /// Drops the previous readings, e.g. after a suspend, during which the
+    /// counters may be reset while `Instant` stands still.
+    pub fn restart(&mut self) {
+        for domain in &mut self.domains {
+            domain.last = None;
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use signal_hook::iterator::Signals;
+
+use crate::config::DaemonConfig;
+
+/// Longest gap allowed between two logged samples in adaptive mode.
+///
+/// The TUI treats gaps of several minutes as sleep, so the daemon must keep
+/// writing a heartbeat well below that even when nothing changes.
+pub const HEARTBEAT_SECS: u64 = 60;
+
+/// Number of recent samples used to judge how noisy the power draw is.
+const VARIANCE_WINDOW: usize = 5;
+
+/// Standard deviation (W) above which the draw counts as spiking.
+const SPIKE_STDDEV_WATTS: f64 = 1.5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SamplingMode {
+    /// Sample every `log_interval_secs`.
+    #[default]
+    Fixed,
+    /// Sample faster during noisy discharge and slower when idle on AC.
+    Adaptive,
+}
+
+/// What the interval policy needs to know about the sample just taken.
+pub struct SampleSummary {
+    pub total_power: f64,
+    pub total_capacity: f64,
+    pub discharging: bool,
+    pub full_on_ac: bool,
+}
+
+/// Picks the delay until the next sample.
+pub struct IntervalPolicy {
+    mode: SamplingMode,
+    base_secs: u64,
+    min_secs: u64,
+    max_secs: u64,
+    current_secs: u64,
+    recent_power: VecDeque<f64>,
+    last_values: Option<(f64, f64)>,
+}
+
+impl IntervalPolicy {
+    pub fn new(config: &DaemonConfig) -> Self {
+        let max_secs = config.max_interval_secs.clamp(1, HEARTBEAT_SECS);
+        let min_secs = config.min_interval_secs.clamp(1, max_secs);
+        // The bounds only apply to adaptive sampling; a fixed interval is
+        // used as configured.
+        let base_secs = match config.sampling {
+            SamplingMode::Fixed => config.log_interval_secs.max(1),
+            SamplingMode::Adaptive => config.log_interval_secs.clamp(min_secs, max_secs),
+        };
+
+        IntervalPolicy {
+            mode: config.sampling,
+            base_secs,
+            min_secs,
+            max_secs,
+            current_secs: base_secs,
+            recent_power: VecDeque::with_capacity(VARIANCE_WINDOW),
+            last_values: None,
+        }
+    }
+
+    /// Adjusts the interval after the sample described by `summary`.
+    pub fn update(&mut self, summary: &SampleSummary) {
+        if self.mode == SamplingMode::Fixed {
+            return;
+        }
+
+        if self.recent_power.len() == VARIANCE_WINDOW {
+            self.recent_power.pop_front();
+        }
+        self.recent_power.push_back(summary.total_power);
+
+        // Compare at the precision the values are logged with.
+        let values = (
+            (summary.total_power * 100.0).round(),
+            summary.total_capacity.round(),
+        );
+        let unchanged = self.last_values == Some(values);
+        self.last_values = Some(values);
+
+        self.current_secs = if summary.discharging && self.power_stddev() > SPIKE_STDDEV_WATTS {
+            self.min_secs
+        } else if summary.full_on_ac {
+            self.max_secs
+        } else if unchanged {
+            (self.current_secs * 2).clamp(self.base_secs, self.max_secs)
+        } else {
+            self.base_secs
+        };
+    }
+
+    /// Returns how long to wait before sampling again.
+    pub fn current(&self) -> Duration {
+        Duration::from_secs(self.current_secs)
+    }
+
+    fn power_stddev(&self) -> f64 {
+        let n = self.recent_power.len() as f64;
+        if n < 2.0 {
+            return 0.0;
+        }
+        let mean = self.recent_power.iter().sum::<f64>() / n;
+        let variance = self
+            .recent_power
+            .iter()
+            .map(|p| (p - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        variance.sqrt()
+    }
+}
+
+/// Schedules samples on wall-clock multiples of the interval, so that slow
+/// reads or writes do not push later samples back.
+#[derive(Default)]
+pub struct Scheduler {
+    previous: Option<Instant>,
+}
+
+impl Scheduler {
+    /// Returns the monotonic deadline matching the next wall-clock multiple
+    /// of `interval` (e.g. :00, :04, :08 for four seconds).
+    pub fn next_deadline(interval: Duration) -> Instant {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let interval_nanos = interval.as_nanos().max(1);
+        let remainder = since_epoch.as_nanos() % interval_nanos;
+        Instant::now() + Duration::from_nanos((interval_nanos - remainder) as u64)
+    }
+
+    /// Marks a tick taken at `now`, after waiting for the deadline of
+    /// `interval`, and returns how many ticks of that interval were skipped
+    /// since the previous one.
+    ///
+    /// `Instant` does not advance while the system is suspended, so only
+    /// time the daemon was actually running counts.
+    pub fn tick(&mut self, now: Instant, interval: Duration) -> u64 {
+        let missed = match self.previous {
+            Some(previous) => {
+                let elapsed = now.duration_since(previous).as_secs_f64();
+                let ticks = (elapsed / interval.as_secs_f64()).round() as u64;
+                ticks.saturating_sub(1)
+            }
+            None => 0,
+        };
+        self.previous = Some(now);
+        missed
+    }
+}
+
+/// Shutdown request raised by SIGTERM/SIGINT that also interrupts waits.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    state: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl Shutdown {
+    /// Requests shutdown as soon as one of `signals` arrives.
+    pub fn on_signals(signals: &[i32]) -> io::Result<Self> {
+        let shutdown = Shutdown::default();
+        let mut signals = Signals::new(signals)?;
+        let handle = shutdown.clone();
+        thread::spawn(move || {
+            if signals.forever().next().is_some() {
+                handle.request();
+            }
+        });
+        Ok(shutdown)
+    }
+
+    pub fn request(&self) {
+        let (requested, condvar) = &*self.state;
+        *requested.lock().unwrap() = true;
+        condvar.notify_all();
+    }
+
+    /// Sleeps until `deadline`; returns `true` early if shutdown was
+    /// requested in the meantime.
+    pub fn wait_until(&self, deadline: Instant) -> bool {
+        let (requested, condvar) = &*self.state;
+        let mut requested = requested.lock().unwrap();
+        while !*requested {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            requested = condvar.wait_timeout(requested, deadline - now).unwrap().0;
+        }
+        *requested
+    }
+}
+
+fn clock_now(clock: libc::clockid_t) -> Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `ts` is a valid, writable timespec.
+    unsafe {
+        libc::clock_gettime(clock, &mut ts);
+    }
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+/// Detects suspends by comparing `CLOCK_BOOTTIME`, which keeps counting while
+/// the system sleeps, with `CLOCK_MONOTONIC`, which does not.
+pub struct SuspendDetector {
+    offset: Duration,
+}
+
+impl SuspendDetector {
+    /// Clock drift below this is jitter rather than a suspend.
+    const MIN_SUSPEND: Duration = Duration::from_secs(1);
+
+    pub fn new() -> Self {
+        SuspendDetector {
+            offset: Self::current_offset(),
+        }
+    }
+
+    fn current_offset() -> Duration {
+        clock_now(libc::CLOCK_BOOTTIME).saturating_sub(clock_now(libc::CLOCK_MONOTONIC))
+    }
+
+    /// Returns how long the system was suspended since the previous call.
+    pub fn check(&mut self) -> Option<Duration> {
+        let offset = Self::current_offset();
+        let suspended = offset.saturating_sub(self.offset);
+        if suspended < Self::MIN_SUSPEND {
+            return None;
+        }
+        self.offset = offset;
+        Some(suspended)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scheduler_counts_missed_ticks() {
+        let mut scheduler = Scheduler::default();
+        let start = Instant::now();
+        let interval = Duration::from_secs(4);
+
+        assert_eq!(scheduler.tick(start, interval), 0);
+        assert_eq!(scheduler.tick(start + interval, interval), 0);
+        assert_eq!(scheduler.tick(start + interval * 4, interval), 2);
+    }
+
+    #[test]
+    fn scheduler_uses_interval_of_the_wait_when_it_grows() {
+        let mut scheduler = Scheduler::default();
+        let start = Instant::now();
+
+        scheduler.tick(start, Duration::from_secs(2));
+        let longer = Duration::from_secs(10);
+        assert_eq!(scheduler.tick(start + longer, longer), 0);
+    }
+
+    #[test]
+    fn scheduler_uses_interval_of_the_wait_when_it_shrinks() {
+        let mut scheduler = Scheduler::default();
+        let start = Instant::now();
+
+        scheduler.tick(start, Duration::from_secs(10));
+        let shorter = Duration::from_secs(2);
+        assert_eq!(scheduler.tick(start + shorter * 5, shorter), 4);
+    }
+
+    fn adaptive_policy() -> IntervalPolicy {
+        IntervalPolicy::new(&DaemonConfig {
+            sampling: SamplingMode::Adaptive,
+            log_interval_secs: 4,
+            min_interval_secs: 1,
+            max_interval_secs: 30,
+            ..DaemonConfig::default()
+        })
+    }
+
+    fn discharging(total_power: f64, total_capacity: f64) -> SampleSummary {
+        SampleSummary {
+            total_power,
+            total_capacity,
+            discharging: true,
+            full_on_ac: false,
+        }
+    }
+
+    #[test]
+    fn fixed_policy_keeps_its_interval() {
+        let mut policy = IntervalPolicy::new(&DaemonConfig {
+            log_interval_secs: 4,
+            ..DaemonConfig::default()
+        });
+        for power in [5.0, 15.0, 5.0, 15.0] {
+            policy.update(&discharging(power, 50.0));
+        }
+        assert_eq!(policy.current(), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn fixed_policy_ignores_adaptive_bounds() {
+        let policy = IntervalPolicy::new(&DaemonConfig {
+            log_interval_secs: 120,
+            max_interval_secs: 30,
+            ..DaemonConfig::default()
+        });
+        assert_eq!(policy.current(), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn adaptive_policy_samples_fastest_while_power_spikes() {
+        let mut policy = adaptive_policy();
+        for power in [5.0, 15.0, 5.0] {
+            policy.update(&discharging(power, 50.0));
+        }
+        assert_eq!(policy.current(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn adaptive_policy_samples_slowest_when_full_on_ac() {
+        let mut policy = adaptive_policy();
+        policy.update(&SampleSummary {
+            total_power: 0.0,
+            total_capacity: 100.0,
+            discharging: false,
+            full_on_ac: true,
+        });
+        assert_eq!(policy.current(), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn adaptive_policy_backs_off_while_readings_do_not_change() {
+        let mut policy = adaptive_policy();
+        let intervals: Vec<u64> = (0..5)
+            .map(|_| {
+                policy.update(&discharging(5.0, 50.0));
+                policy.current().as_secs()
+            })
+            .collect();
+        assert_eq!(intervals, [4, 8, 16, 30, 30]);
+
+        policy.update(&discharging(5.5, 50.0));
+        assert_eq!(policy.current(), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn adaptive_policy_never_exceeds_the_heartbeat() {
+        let policy = IntervalPolicy::new(&DaemonConfig {
+            sampling: SamplingMode::Adaptive,
+            log_interval_secs: 600,
+            max_interval_secs: 600,
+            ..DaemonConfig::default()
+        });
+        assert_eq!(policy.current(), Duration::from_secs(HEARTBEAT_SECS));
+    }
+}
@@ -1,13 +1,14 @@
 use ratatui::{
+    Frame,
     layout::{Alignment, Constraint, Layout, Rect},
     style::{Color, Style, Stylize},
     symbols::Marker,
     text::{Line, Span},
     widgets::{Axis, Block, Borders, Chart, Clear, Dataset, GraphType, Paragraph},
-    Frame,
 };
 
-use crate::app::App;
+use crate::app::{App, Overlay, SleepKind};
+use crate::data::BatteryRecord;
 
 pub fn format_duration(secs: f64) -> String {
     let total_secs = secs as u64;
@@ -21,10 +22,50 @@ pub fn format_duration(secs: f64) -> String {
     }
 }
 
+/// Summarises the optional voltage, current and energy (or charge) readings
+/// of a record, e.g. `12.31V 1.05A 41.2/57.0Wh`.
+fn format_electrical(record: &BatteryRecord) -> Option<String> {
+    let mut parts = Vec::new();
+
+    if let Some(voltage) = record.voltage {
+        parts.push(format!("{:.2}V", voltage));
+    }
+    if let Some(current) = record.current {
+        parts.push(format!("{:.2}A", current));
+    }
+    match (
+        record.energy_now,
+        record.energy_full,
+        record.charge_now,
+        record.charge_full,
+    ) {
+        (Some(now), Some(full), _, _) => parts.push(format!("{:.1}/{:.1}Wh", now, full)),
+        (Some(now), None, _, _) => parts.push(format!("{:.1}Wh", now)),
+        (None, _, Some(now), Some(full)) => parts.push(format!("{:.2}/{:.2}Ah", now, full)),
+        (None, _, Some(now), None) => parts.push(format!("{:.2}Ah", now)),
+        _ => {}
+    }
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" "))
+    }
+}
+
 pub fn draw(frame: &mut Frame, app: &App) {
     let chunks = Layout::vertical([Constraint::Min(10), Constraint::Length(4)]).split(frame.area());
 
-    draw_chart(frame, app, chunks[0]);
+    if app.show_health {
+        draw_health_chart(frame, app, chunks[0]);
+    } else if app.show_processes {
+        let columns =
+            Layout::horizontal([Constraint::Min(40), Constraint::Length(36)]).split(chunks[0]);
+        draw_chart(frame, app, columns[0]);
+        draw_process_panel(frame, app, columns[1]);
+    } else {
+        draw_chart(frame, app, chunks[0]);
+    }
     draw_status_bar(frame, app, chunks[1]);
 
     if app.show_service_warning {
@@ -36,6 +77,15 @@ pub fn draw(frame: &mut Frame, app: &App) {
     }
 }
 
+fn sleep_color(kind: SleepKind) -> Color {
+    match kind {
+        SleepKind::Suspend => Color::Magenta,
+        SleepKind::Hibernate => Color::LightBlue,
+        SleepKind::PowerOff => Color::DarkGray,
+        SleepKind::DaemonDown => Color::LightRed,
+    }
+}
+
 fn draw_chart(frame: &mut Frame, app: &App, area: Rect) {
     let chart_data = app.chart_data();
 
@@ -45,21 +95,64 @@ fn draw_chart(frame: &mut Frame, app: &App, area: Rect) {
 
     let x_labels = chart_data.x_labels.clone();
 
-    let sleep_lines: Vec<Vec<(f64, f64)>> = chart_data
+    let sleep_lines: Vec<(SleepKind, Vec<(f64, f64)>)> = chart_data
         .sleep_markers
         .iter()
-        .map(|(x, _)| (0..=20).map(|i| (*x, y_max * i as f64 / 20.0)).collect())
+        .map(|(x, sp)| {
+            let line = (0..=20).map(|i| (*x, y_max * i as f64 / 20.0)).collect();
+            (sp.kind, line)
+        })
         .collect();
 
     let mut datasets: Vec<Dataset> = Vec::new();
-    for (i, line_data) in sleep_lines.iter().enumerate() {
-        let name = if i == 0 { "Sleep" } else { "" };
+    let mut named_kinds: Vec<SleepKind> = Vec::new();
+    for (kind, line_data) in &sleep_lines {
+        let name = if named_kinds.contains(kind) {
+            ""
+        } else {
+            named_kinds.push(*kind);
+            kind.label()
+        };
         datasets.push(
             Dataset::default()
                 .name(name)
                 .marker(Marker::Braille)
                 .graph_type(GraphType::Line)
-                .style(Style::default().fg(Color::Magenta))
+                .style(Style::default().fg(sleep_color(*kind)))
+                .data(line_data),
+        );
+    }
+
+    let adapter_lines: Vec<(bool, Vec<(f64, f64)>)> = chart_data
+        .adapter_markers
+        .iter()
+        .map(|(x, plugged)| {
+            let line = (0..=20)
+                .step_by(2)
+                .map(|i| (*x, y_max * i as f64 / 20.0))
+                .collect();
+            (*plugged, line)
+        })
+        .collect();
+
+    let mut named_plug = false;
+    let mut named_unplug = false;
+    for (plugged, line_data) in &adapter_lines {
+        let (name, color) = if *plugged {
+            let name = if named_plug { "" } else { "Plugged" };
+            named_plug = true;
+            (name, Color::Green)
+        } else {
+            let name = if named_unplug { "" } else { "Unplugged" };
+            named_unplug = true;
+            (name, Color::Red)
+        };
+        datasets.push(
+            Dataset::default()
+                .name(name)
+                .marker(Marker::Dot)
+                .graph_type(GraphType::Scatter)
+                .style(Style::default().fg(color))
                 .data(line_data),
         );
     }
@@ -78,13 +171,77 @@ fn draw_chart(frame: &mut Frame, app: &App, area: Rect) {
             .style(Style::default().fg(Color::Yellow))
             .data(&chart_data.power_data),
     );
+    if !chart_data.soc_power_data.is_empty() {
+        datasets.push(
+            Dataset::default()
+                .name("SoC (W)")
+                .marker(Marker::Braille)
+                .style(Style::default().fg(Color::LightGreen))
+                .data(&chart_data.soc_power_data),
+        );
+    }
+    // Both temperatures share one scale, like the overlays.
+    if app.show_temperature {
+        let temperatures = [
+            ("Battery", Color::LightRed, &chart_data.temperature_data),
+            ("CPU", Color::Red, &chart_data.cpu_temperature_data),
+        ];
+        for (name, color, data) in temperatures {
+            if !data.is_empty() {
+                datasets.push(
+                    Dataset::default()
+                        .name(format!(
+                            "{} (100 = {:.0} °C)",
+                            name, chart_data.temperature_max
+                        ))
+                        .marker(Marker::Braille)
+                        .style(Style::default().fg(color))
+                        .data(data),
+                );
+            }
+        }
+    }
+
+    if !chart_data.overlay_data.is_empty() {
+        let name = match app.overlay {
+            Overlay::CpuFreq => format!("CPU freq (100 = {:.0} MHz)", chart_data.overlay_max),
+            Overlay::Load => format!("Load (100 = {:.2})", chart_data.overlay_max),
+            _ => format!("{} (%)", app.overlay.label()),
+        };
+        datasets.push(
+            Dataset::default()
+                .name(name)
+                .marker(Marker::Braille)
+                .style(Style::default().fg(Color::Blue))
+                .data(&chart_data.overlay_data),
+        );
+    }
+
+    let inspect_line: Vec<(f64, f64)> = chart_data
+        .inspect_x
+        .map(|x| (0..=20).map(|i| (x, y_max * i as f64 / 20.0)).collect())
+        .unwrap_or_default();
+    if !inspect_line.is_empty() {
+        datasets.push(
+            Dataset::default()
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::White))
+                .data(&inspect_line),
+        );
+    }
 
     let date_str = app.current_date.format("%Y-%m-%d").to_string();
     let today_marker = if app.is_today() { " (Live)" } else { "" };
+    let battery_marker = app
+        .battery_label()
+        .map(|label| format!(" {}", label))
+        .unwrap_or_default();
     let title = format!(
-        " Watt Monitor - {} [{}]{} ",
+        " Watt Monitor - {} [{}]{}{} ",
         date_str,
         app.view_mode_label(),
+        battery_marker,
         today_marker
     );
     let chart = Chart::new(datasets)
@@ -105,33 +262,26 @@ fn draw_chart(frame: &mut Frame, app: &App, area: Rect) {
         );
 
     frame.render_widget(chart, area);
+    draw_adapter_labels(
+        frame,
+        area,
+        chart_data.time_range,
+        &chart_data.adapter_markers,
+    );
 
-    let plot_top = area.y + 1;
-    let plot_bottom = area.y + area.height - 3;
-    let plot_height = plot_bottom.saturating_sub(plot_top);
-    let right_x = area.x + area.width - 1;
-
-    let power_labels = [
-        (plot_bottom, "0W".to_string()),
-        (
-            plot_top + plot_height / 2,
+    draw_right_axis_labels(
+        frame,
+        area,
+        [
+            "0W".to_string(),
             format!("{:.0}W", power_max / 2.0),
-        ),
-        (plot_top, format!("{:.0}W", power_max)),
-    ];
+            format!("{:.0}W", power_max),
+        ],
+        Color::Yellow,
+    );
 
-    for (y_pos, label) in power_labels {
-        let label_len = label.len() as u16;
-        let label_x = right_x.saturating_sub(label_len);
-        if label_x >= area.x && y_pos >= area.y && y_pos < area.y + area.height {
-            let label_area = Rect::new(label_x, y_pos, label_len, 1);
-            let label_widget = Paragraph::new(label).style(
-                Style::default()
-                    .fg(Color::Yellow)
-                    .add_modifier(ratatui::style::Modifier::BOLD),
-            );
-            frame.render_widget(label_widget, label_area);
-        }
+    if let Some(record) = app.inspected_record() {
+        draw_inspector(frame, record, area);
     }
 
     let plot_left = area.x + 7;
@@ -161,12 +311,280 @@ fn draw_chart(frame: &mut Frame, app: &App, area: Rect) {
         {
             let label_area = Rect::new(label_x, label_y, label_len, 1);
             let label_widget =
-                Paragraph::new(wake_label).style(Style::default().fg(Color::Magenta));
+                Paragraph::new(wake_label).style(Style::default().fg(sleep_color(sp.kind)));
             frame.render_widget(label_widget, label_area);
         }
     }
 }
 
+/// Shows every logged reading of the inspected sample in a box at the top
+/// left of the plot.
+fn draw_inspector(frame: &mut Frame, record: &BatteryRecord, area: Rect) {
+    let mut rows: Vec<(&str, String)> = vec![
+        ("Time", record.time.format("%H:%M:%S").to_string()),
+        ("Status", record.status.clone()),
+        ("Capacity", format!("{:.1}%", record.capacity)),
+        ("Power", format!("{:.2} W", record.power)),
+    ];
+    let optional = [
+        ("SoC", record.soc_power().map(|p| format!("{:.2} W", p))),
+        ("Temp", record.temperature.map(|t| format!("{:.1}°C", t))),
+        (
+            "CPU temp",
+            record.cpu_temperature.map(|t| format!("{:.1}°C", t)),
+        ),
+        (
+            "Brightness",
+            record.brightness.map(|b| format!("{:.0}%", b)),
+        ),
+        (
+            "CPU freq",
+            record.cpu_freq_mhz.map(|f| format!("{:.0} MHz", f)),
+        ),
+        ("Load", record.load.map(|l| format!("{:.2}", l))),
+    ];
+    rows.extend(
+        optional
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name, value))),
+    );
+
+    let lines: Vec<Line> = rows
+        .into_iter()
+        .map(|(name, value)| Line::from(vec![format!("{:<11}", name).dark_gray(), value.white()]))
+        .collect();
+
+    let width = 26.min(area.width.saturating_sub(9));
+    let height = (lines.len() as u16 + 2).min(area.height.saturating_sub(3));
+    let inspector_area = Rect::new(area.x + 8, area.y + 1, width, height);
+    frame.render_widget(Clear, inspector_area);
+    frame.render_widget(
+        Paragraph::new(lines).block(
+            Block::bordered()
+                .title(" Inspector ")
+                .border_style(Style::default().fg(Color::White)),
+        ),
+        inspector_area,
+    );
+}
+
+/// Labels the bottom, middle and top of the plot along its right edge, for a
+/// series scaled onto the left axis.
+fn draw_right_axis_labels(frame: &mut Frame, area: Rect, labels: [String; 3], color: Color) {
+    let plot_top = area.y + 1;
+    let plot_bottom = area.y + area.height - 3;
+    let plot_height = plot_bottom.saturating_sub(plot_top);
+    let right_x = area.x + area.width - 1;
+
+    let positions = [plot_bottom, plot_top + plot_height / 2, plot_top];
+    for (y_pos, label) in positions.into_iter().zip(labels) {
+        let label_len = label.len() as u16;
+        let label_x = right_x.saturating_sub(label_len);
+        if label_x >= area.x && y_pos >= area.y && y_pos < area.y + area.height {
+            let label_area = Rect::new(label_x, y_pos, label_len, 1);
+            let label_widget = Paragraph::new(label).style(
+                Style::default()
+                    .fg(color)
+                    .add_modifier(ratatui::style::Modifier::BOLD),
+            );
+            frame.render_widget(label_widget, label_area);
+        }
+    }
+}
+
+/// Plots each battery's full-charge capacity (as % of design) and its cycle
+/// count, scaled to the highest count, over the days in the health log.
+fn draw_health_chart(frame: &mut Frame, app: &App, area: Rect) {
+    const HEALTH_COLORS: [Color; 3] = [Color::Green, Color::Cyan, Color::LightBlue];
+    const CYCLE_COLORS: [Color; 3] = [Color::Magenta, Color::Yellow, Color::LightRed];
+
+    let health = app.health_chart_data();
+    let block = Block::bordered().title(" Watt Monitor - Battery Health ");
+
+    if health.series.is_empty() {
+        let text = vec![
+            Line::from(""),
+            Line::from("No battery health data yet.".yellow()),
+            Line::from("The daemon records it once a day while running.".dark_gray()),
+        ];
+        frame.render_widget(
+            Paragraph::new(text)
+                .alignment(Alignment::Center)
+                .block(block),
+            area,
+        );
+        return;
+    }
+
+    let cycle_scale = if health.max_cycles > 0.0 {
+        100.0 / health.max_cycles
+    } else {
+        0.0
+    };
+    let scaled_cycles: Vec<Vec<(f64, f64)>> = health
+        .series
+        .iter()
+        .map(|s| {
+            s.cycle_data
+                .iter()
+                .map(|(x, cycles)| (*x, cycles * cycle_scale))
+                .collect()
+        })
+        .collect();
+
+    let mut datasets: Vec<Dataset> = Vec::new();
+    for (i, (series, cycles)) in health.series.iter().zip(&scaled_cycles).enumerate() {
+        datasets.push(
+            Dataset::default()
+                .name(match series.capacity_reference {
+                    Some((full, unit)) => {
+                        format!("{} full (100 = {:.1} {})", series.battery, full, unit)
+                    }
+                    None => format!("{} health (%)", series.battery),
+                })
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(HEALTH_COLORS[i % HEALTH_COLORS.len()]))
+                .data(&series.health_data),
+        );
+        if !cycles.is_empty() {
+            datasets.push(
+                Dataset::default()
+                    .name(format!("{} cycles", series.battery))
+                    .marker(Marker::Braille)
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(CYCLE_COLORS[i % CYCLE_COLORS.len()]))
+                    .data(cycles),
+            );
+        }
+    }
+
+    let chart = Chart::new(datasets)
+        .block(block)
+        .x_axis(
+            Axis::default()
+                .title("Date")
+                .style(Style::default().fg(Color::Gray))
+                .bounds([health.day_range.0, health.day_range.1])
+                .labels(health.x_labels.clone()),
+        )
+        .y_axis(
+            Axis::default()
+                .title(
+                    if health.series.iter().all(|s| s.capacity_reference.is_none()) {
+                        "Health(%)"
+                    } else {
+                        "Capacity(%)"
+                    },
+                )
+                .style(Style::default().fg(Color::Green))
+                .bounds([0.0, 100.0])
+                .labels(vec!["0".green().bold(), "50".green(), "100".green().bold()]),
+        );
+    frame.render_widget(chart, area);
+
+    if health.max_cycles > 0.0 {
+        draw_right_axis_labels(
+            frame,
+            area,
+            [
+                "0".to_string(),
+                format!("{:.0}", health.max_cycles / 2.0),
+                format!("{:.0} cycles", health.max_cycles),
+            ],
+            Color::Magenta,
+        );
+    }
+}
+
+/// Lists the commands that used the most energy in the viewed time range,
+/// with their share of the energy attributed to all listed processes.
+fn draw_process_panel(frame: &mut Frame, app: &App, area: Rect) {
+    const COMMAND_WIDTH: usize = 15;
+
+    let consumers = app.top_consumers();
+    let block = Block::bordered().title(" Top Consumers ");
+
+    if consumers.is_empty() {
+        let text = vec![
+            Line::from(""),
+            Line::from("No process data for this view.".yellow()),
+            Line::from(""),
+            Line::from("Set `processes = true` in the".dark_gray()),
+            Line::from("[daemon] config to collect it.".dark_gray()),
+        ];
+        frame.render_widget(
+            Paragraph::new(text)
+                .alignment(Alignment::Center)
+                .block(block),
+            area,
+        );
+        return;
+    }
+
+    let total: f64 = consumers.iter().map(|(_, energy, _)| energy).sum();
+    let rows = area.height.saturating_sub(2) as usize;
+    let lines: Vec<Line> = consumers
+        .iter()
+        .take(rows)
+        .enumerate()
+        .map(|(i, (command, energy, _))| {
+            let command: String = command.chars().take(COMMAND_WIDTH).collect();
+            let share = if total > 0.0 {
+                energy / total * 100.0
+            } else {
+                0.0
+            };
+            Line::from(vec![
+                format!("{:>2}. ", i + 1).dark_gray(),
+                format!("{:<width$} ", command, width = COMMAND_WIDTH).white(),
+                format!("{:>6.2}Wh ", energy / 3600.0).yellow(),
+                format!("{:>3.0}%", share).cyan(),
+            ])
+        })
+        .collect();
+
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_adapter_labels(
+    frame: &mut Frame,
+    area: Rect,
+    time_range: (f64, f64),
+    adapter_markers: &[(f64, bool)],
+) {
+    let (time_min, time_max) = time_range;
+    let plot_left = area.x + 7;
+    let plot_right = area.x + area.width - 2;
+    let plot_width = plot_right.saturating_sub(plot_left) as f64;
+    let label_y = area.y + 1;
+
+    for (compressed_x, plugged) in adapter_markers {
+        let x_ratio = if time_max > time_min {
+            (compressed_x - time_min) / (time_max - time_min)
+        } else {
+            0.0
+        };
+        let screen_x = plot_left + (plot_width * x_ratio) as u16;
+
+        let (label, color) = if *plugged {
+            ("+AC", Color::Green)
+        } else {
+            ("-AC", Color::Red)
+        };
+        let label_len = label.len() as u16;
+        let label_x = screen_x.saturating_sub(label_len / 2);
+
+        if label_x >= area.x && label_x + label_len <= area.x + area.width {
+            let label_area = Rect::new(label_x, label_y, label_len, 1);
+            frame.render_widget(
+                Paragraph::new(label).style(Style::default().fg(color)),
+                label_area,
+            );
+        }
+    }
+}
+
 fn draw_status_bar(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
     let status = app.latest_status().unwrap_or("N/A");
     let status_span: Span = match status {
@@ -176,13 +594,23 @@ fn draw_status_bar(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
         _ => status.bold(),
     };
 
+    let status_span = match app.latest_adapter() {
+        Some(adapter) => {
+            Span::from(format!("{} ({})", status_span.content, adapter)).style(status_span.style)
+        }
+        None => status_span,
+    };
+
     let capacity = app
         .latest_capacity()
         .map(|c| format!("{:.1}%", c))
         .unwrap_or_else(|| "N/A".to_string());
     let power = app
         .latest_power()
-        .map(|p| format!("{:.2}W", p))
+        .map(|p| match app.latest_power_estimate_source() {
+            Some(source) => format!("~{:.2}W ({})", p, source),
+            None => format!("{:.2}W", p),
+        })
         .unwrap_or_else(|| "N/A".to_string());
 
     let mut line1 = vec![
@@ -192,16 +620,47 @@ fn draw_status_bar(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
         capacity.cyan().bold(),
         " | Power: ".into(),
         power.yellow().bold(),
-        " | View: ".into(),
-        app.view_mode_label().green(),
     ];
 
+    if let Some(soc_power) = app.latest_soc_power() {
+        line1.push(" | SoC: ".into());
+        line1.push(format!("{:.2}W", soc_power).light_green().bold());
+    }
+
+    if let Some(electrical) = app.latest_record().and_then(format_electrical) {
+        line1.push(" | ".into());
+        line1.push(electrical.white());
+    }
+
+    if app.show_temperature
+        && let Some(record) = app.latest_record()
+    {
+        let temperatures: Vec<String> =
+            [("", record.temperature), ("CPU ", record.cpu_temperature)]
+                .into_iter()
+                .filter_map(|(prefix, t)| t.map(|t| format!("{}{:.1}°C", prefix, t)))
+                .collect();
+        if !temperatures.is_empty() {
+            line1.push(" | ".into());
+            line1.push(temperatures.join(" ").light_red());
+        }
+    }
+
+    let view = if app.show_health {
+        "Health".to_string()
+    } else {
+        app.view_mode_label()
+    };
+    line1.extend([" | View: ".into(), view.green()]);
+
     let line1_width: usize = line1.iter().map(|s| s.content.len()).sum();
     let available_width = area.width as usize;
-    if available_width > line1_width + 20 {
-        let padding = available_width - line1_width - 15;
+    let key_hint = "| ←→ Tab b t o i [ ] p w h q ";
+    let key_hint_width = key_hint.chars().count();
+    if available_width > line1_width + key_hint_width + 19 {
+        let padding = available_width - line1_width - key_hint_width - 14;
         line1.push(Span::raw(" ".repeat(padding)));
-        line1.push("| ←→ Tab h q ".dark_gray());
+        line1.push(key_hint.dark_gray());
     }
 
     let mut line2 = vec![];
@@ -254,13 +713,14 @@ fn draw_status_bar(frame: &mut Frame, app: &App, area: ratatui::layout::Rect) {
             format!("{:.1}%/h", rate)
         };
 
+        let color = sleep_color(sleep.kind);
         line2.extend(vec![
-            " Last Sleep: ".into(),
-            duration.magenta(),
+            format!(" Last {}: ", sleep.kind.label()).into(),
+            duration.fg(color),
             " (".into(),
-            diff_str.magenta().bold(),
+            diff_str.fg(color).bold(),
             ", ".into(),
-            rate_str.magenta(),
+            rate_str.fg(color),
             ")".into(),
         ]);
     }
@@ -0,0 +1,986 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixDatagram, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+/// A fake sysfs tree plus isolated data, runtime and temp directories.
+struct Sandbox {
+    root: TempDir,
+}
+
+impl Sandbox {
+    fn new() -> Self {
+        let root = tempfile::tempdir().unwrap();
+        for dir in ["sys/class/power_supply", "config", "data", "run", "tmp"] {
+            fs::create_dir_all(root.path().join(dir)).unwrap();
+        }
+        Sandbox { root }
+    }
+
+    fn path(&self, relative: &str) -> PathBuf {
+        self.root.path().join(relative)
+    }
+
+    fn add_supply(&self, name: &str, attrs: &[(&str, &str)]) {
+        let dir = self.path("sys/class/power_supply").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        for (attr, value) in attrs {
+            fs::write(dir.join(attr), format!("{}\n", value)).unwrap();
+        }
+    }
+
+    /// Adds the BAT0 most tests log: discharging at 5 W, half full.
+    fn add_discharging_battery(&self) {
+        self.add_supply(
+            "BAT0",
+            &[
+                ("type", "Battery"),
+                ("status", "Discharging"),
+                ("capacity", "50"),
+                ("power_now", "5000000"),
+            ],
+        );
+    }
+
+    fn write_config(&self, content: &str) {
+        fs::create_dir_all(self.path("config/watt-monitor")).unwrap();
+        fs::write(self.path("config/watt-monitor/config.toml"), content).unwrap();
+    }
+
+    fn live_log(&self) -> PathBuf {
+        self.path("run/watt-monitor/battery_watt_history.csv")
+    }
+
+    fn daemon(&self) -> Command {
+        self.command("daemon")
+    }
+
+    fn command(&self, subcommand: &str) -> Command {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_watt-monitor"));
+        cmd.arg(subcommand)
+            .env("HOME", self.root.path())
+            .env("XDG_CONFIG_HOME", self.path("config"))
+            .env("XDG_DATA_HOME", self.path("data"))
+            .env("XDG_RUNTIME_DIR", self.path("run"))
+            .env("TMPDIR", self.path("tmp"))
+            .env_remove("WATT_MONITOR_SYSFS")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        cmd
+    }
+}
+
+fn wait_for_rows(path: &Path, rows: usize) -> Vec<String> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let lines: Vec<String> = fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        if lines.len() > rows {
+            return lines;
+        }
+        assert!(Instant::now() < deadline, "daemon did not write {:?}", path);
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Looks up `name` in a CSV row by the position of that column in `header`.
+fn field<'a>(header: &str, row: &'a str, name: &str) -> &'a str {
+    let index = header
+        .split(',')
+        .position(|column| column == name)
+        .unwrap_or_else(|| panic!("missing column {}", name));
+    row.split(',').nth(index).unwrap_or_default()
+}
+
+/// Sends SIGTERM and checks that the daemon exits cleanly without waiting
+/// out its sampling interval.
+fn stop(mut child: Child) {
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+    }
+    let deadline = Instant::now() + Duration::from_secs(2);
+    loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            assert!(status.success());
+            return;
+        }
+        assert!(Instant::now() < deadline, "daemon ignored SIGTERM");
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn logs_every_system_battery_from_sysfs_root() {
+    let sandbox = Sandbox::new();
+    sandbox.add_supply(
+        "BAT0",
+        &[
+            ("type", "Battery"),
+            ("status", "Discharging"),
+            ("capacity", "87"),
+            ("power_now", "12340000"),
+            ("voltage_now", "11800000"),
+            ("energy_now", "45000000"),
+            ("energy_full", "52000000"),
+        ],
+    );
+    sandbox.add_supply(
+        "CMB1",
+        &[
+            ("type", "Battery"),
+            ("status", "Charging"),
+            ("capacity", "40"),
+            ("current_now", "2000000"),
+            ("voltage_now", "12000000"),
+        ],
+    );
+    sandbox.add_supply("AC", &[("type", "Mains"), ("online", "0")]);
+    sandbox.add_supply(
+        "hidpp_battery_0",
+        &[
+            ("type", "Battery"),
+            ("scope", "Device"),
+            ("status", "Discharging"),
+            ("capacity", "55"),
+        ],
+    );
+    sandbox.write_config("[daemon]\nflush_interval_secs = 0\n");
+
+    let child = sandbox
+        .daemon()
+        .arg("--sysfs-root")
+        .arg(sandbox.path("sys"))
+        .spawn()
+        .unwrap();
+    let lines = wait_for_rows(&sandbox.live_log(), 3);
+    stop(child);
+
+    let header = lines[0].as_str();
+    assert!(header.starts_with("Time,Status,Capacity(%),Power(W),PowerSource"));
+
+    // Each session starts with a marker row carrying only the boot ID.
+    let start = lines[1].as_str();
+    assert_eq!(field(header, start, "Event"), "start");
+    assert_eq!(field(header, start, "Status"), "");
+
+    let bat0 = lines[2].as_str();
+    assert_eq!(field(header, bat0, "Battery"), "BAT0");
+    assert_eq!(field(header, bat0, "Status"), "Discharging");
+    assert_eq!(field(header, bat0, "Capacity(%)"), "87");
+    assert_eq!(field(header, bat0, "Power(W)"), "12.34");
+    assert_eq!(field(header, bat0, "PowerSource"), "power_now");
+    assert_eq!(field(header, bat0, "Voltage(V)"), "11.800");
+    assert_eq!(field(header, bat0, "EnergyFull(Wh)"), "52.000");
+    assert_eq!(field(header, bat0, "Adapter"), "Offline");
+    assert_eq!(field(header, bat0, "Missed"), "0");
+    assert_eq!(field(header, bat0, "Event"), "");
+    let boot_id = fs::read_to_string("/proc/sys/kernel/random/boot_id").unwrap_or_default();
+    assert_eq!(field(header, start, "BootId"), boot_id.trim());
+    assert_eq!(field(header, bat0, "BootId"), boot_id.trim());
+
+    // Samples land on wall-clock multiples of the 4 second interval.
+    let seconds: u32 = field(header, bat0, "Time")[17..].parse().unwrap();
+    assert_eq!(seconds % 4, 0);
+
+    let cmb1 = lines[3].as_str();
+    assert_eq!(field(header, cmb1, "Battery"), "CMB1");
+    assert_eq!(field(header, cmb1, "Power(W)"), "24.00");
+    assert_eq!(field(header, cmb1, "PowerSource"), "current_voltage");
+
+    assert!(lines.iter().all(|line| !line.contains("hidpp_battery_0")));
+}
+
+#[test]
+fn reads_sysfs_root_from_environment_and_usb_pd_adapter() {
+    let sandbox = Sandbox::new();
+    sandbox.add_supply(
+        "BAT1",
+        &[
+            ("type", "Battery"),
+            ("status", "Full"),
+            ("capacity", "100"),
+            ("power_now", "0"),
+        ],
+    );
+    sandbox.add_supply("ACAD", &[("type", "Mains"), ("online", "0")]);
+    sandbox.add_supply(
+        "ucsi-source-psy-USBC000:001",
+        &[
+            ("type", "USB"),
+            ("online", "1"),
+            ("usb_type", "C [PD] PD_PPS"),
+            ("voltage_max", "20000000"),
+        ],
+    );
+
+    sandbox.write_config("[daemon]\nlog_interval_secs = 1\nflush_interval_secs = 0\n");
+
+    let child = sandbox
+        .daemon()
+        .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+        .spawn()
+        .unwrap();
+    let lines = wait_for_rows(&sandbox.live_log(), 2);
+    stop(child);
+
+    let (header, row) = (lines[0].as_str(), lines[2].as_str());
+    assert!(row.contains(",Full,100,0.00,power_now,"));
+    assert_eq!(field(header, row, "Adapter"), "USB-PD");
+    assert_eq!(field(header, row, "AdapterMax(V)"), "20.0");
+}
+
+#[test]
+fn fails_without_battery() {
+    let sandbox = Sandbox::new();
+    sandbox.add_supply("AC", &[("type", "Mains"), ("online", "1")]);
+
+    let status = sandbox
+        .daemon()
+        .arg("--sysfs-root")
+        .arg(sandbox.path("sys"))
+        .status()
+        .unwrap();
+
+    assert!(!status.success());
+    assert!(!sandbox.live_log().exists());
+}
+
+#[test]
+fn reads_settings_from_config_file() {
+    let sandbox = Sandbox::new();
+    sandbox.add_discharging_battery();
+    let live_log = sandbox.path("custom/live.csv");
+    fs::create_dir_all(live_log.parent().unwrap()).unwrap();
+    sandbox.write_config(&format!(
+        "live_log = {:?}\n\n[daemon]\nlog_interval_secs = 1\nflush_interval_secs = 0\nsysfs_root = {:?}\n",
+        live_log,
+        sandbox.path("sys")
+    ));
+
+    let child = sandbox.daemon().spawn().unwrap();
+    let lines = wait_for_rows(&live_log, 3);
+    stop(child);
+
+    assert!(lines[2].contains(",Discharging,50,5.00,power_now,"));
+    assert!(!sandbox.live_log().exists());
+}
+
+#[test]
+fn finishes_interrupted_rotation_at_startup() {
+    let sandbox = Sandbox::new();
+    sandbox.add_discharging_battery();
+    sandbox.write_config("[daemon]\nlog_interval_secs = 1\nflush_interval_secs = 0\n");
+
+    // A rotation that copied the first day into its archive, left a partial
+    // temporary file for the second day and never truncated the live log.
+    let first = "2024-03-01 23:59:56,Discharging,80,5.00";
+    let second = "2024-03-02 00:00:00,Discharging,79,5.00";
+    let archive_dir = sandbox.path("data/watt-monitor");
+    fs::create_dir_all(&archive_dir).unwrap();
+    fs::write(
+        archive_dir.join("2024-03-01.csv"),
+        format!("Time,Status,Capacity(%),Power(W)\n{}\n", first),
+    )
+    .unwrap();
+    fs::write(archive_dir.join("2024-03-02.csv.tmp"), "Time,Sta").unwrap();
+    fs::create_dir_all(sandbox.live_log().parent().unwrap()).unwrap();
+    fs::write(
+        sandbox.live_log(),
+        format!("Time,Status,Capacity(%),Power(W)\n{}\n{}\n", first, second),
+    )
+    .unwrap();
+
+    let child = sandbox
+        .daemon()
+        .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+        .spawn()
+        .unwrap();
+    let lines = wait_for_rows(&sandbox.live_log(), 3);
+    stop(child);
+
+    assert!(lines.iter().all(|line| !line.starts_with("2024-03")));
+
+    let first_archive = fs::read_to_string(archive_dir.join("2024-03-01.csv")).unwrap();
+    assert_eq!(first_archive.matches(first).count(), 1);
+    let second_archive = fs::read_to_string(archive_dir.join("2024-03-02.csv")).unwrap();
+    assert!(second_archive.starts_with("Time,Status,Capacity(%),Power(W),PowerSource"));
+    assert!(second_archive.contains(second));
+    assert!(!archive_dir.join("2024-03-02.csv.tmp").exists());
+
+    // Shutdown moves the new samples into today's archive.
+    let live = fs::read_to_string(sandbox.live_log()).unwrap();
+    assert_eq!(live.lines().count(), 1);
+    let today = fs::read_dir(&archive_dir)
+        .unwrap()
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| !path.to_string_lossy().contains("2024-03"))
+        .unwrap();
+    assert!(
+        fs::read_to_string(today)
+            .unwrap()
+            .contains(",Discharging,50,5.00,")
+    );
+}
+
+#[test]
+fn checkpoints_live_log_into_archive() {
+    let sandbox = Sandbox::new();
+    sandbox.add_discharging_battery();
+    sandbox.write_config("[daemon]\nlog_interval_secs = 1\ncheckpoint_interval_secs = 1\n");
+
+    let child = sandbox
+        .daemon()
+        .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+        .spawn()
+        .unwrap();
+
+    let archive_dir = sandbox.path("data/watt-monitor");
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let archive = loop {
+        let archive = fs::read_dir(&archive_dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .find(|path| path.extension().is_some_and(|e| e == "csv"));
+        if let Some(archive) = archive {
+            break archive;
+        }
+        assert!(Instant::now() < deadline, "daemon did not checkpoint");
+        thread::sleep(Duration::from_millis(100));
+    };
+    stop(child);
+
+    assert!(
+        fs::read_to_string(archive)
+            .unwrap()
+            .contains(",Discharging,50,5.00,")
+    );
+}
+
+/// Sends one request to the daemon's socket and returns the JSON rows of the
+/// answer.
+fn request(sandbox: &Sandbox, request: &str) -> Vec<serde_json::Value> {
+    let mut stream = UnixStream::connect(sandbox.path("run/watt-monitor/daemon.sock")).unwrap();
+    writeln!(stream, "{}", request).unwrap();
+    BufReader::new(stream)
+        .lines()
+        .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+        .collect()
+}
+
+#[test]
+fn streams_buffered_samples_over_socket() {
+    let sandbox = Sandbox::new();
+    sandbox.add_discharging_battery();
+    sandbox.write_config("[daemon]\nlog_interval_secs = 1\nflush_interval_secs = 3600\n");
+
+    let child = sandbox
+        .daemon()
+        .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+        .spawn()
+        .unwrap();
+
+    let socket = sandbox.path("run/watt-monitor/daemon.sock");
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut stream = loop {
+        if let Ok(stream) = UnixStream::connect(&socket) {
+            break stream;
+        }
+        assert!(Instant::now() < deadline, "daemon did not open its socket");
+        thread::sleep(Duration::from_millis(100));
+    };
+    writeln!(stream, "{{\"request\":\"subscribe\"}}").unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+
+    // Unflushed rows come first, then new samples as they are taken.
+    let mut lines = BufReader::new(stream).lines();
+    let mut next_row =
+        || -> serde_json::Value { serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap() };
+    assert_eq!(next_row()["event"], "start");
+    for _ in 0..2 {
+        let sample = next_row();
+        assert_eq!(sample["battery"], "BAT0");
+        assert_eq!(sample["capacity"], 50.0);
+        assert_eq!(sample["power"], 5.0);
+    }
+
+    // Neither a client that never sends its request nor a subscriber that
+    // never reads holds up other requests.
+    let _idle = UnixStream::connect(&socket).unwrap();
+    let mut stalled = UnixStream::connect(&socket).unwrap();
+    writeln!(stalled, "{{\"request\":\"subscribe\"}}").unwrap();
+    let started = Instant::now();
+    let current = request(&sandbox, r#"{"request":"current"}"#);
+    assert!(started.elapsed() < Duration::from_millis(500));
+    assert_eq!(current.len(), 1);
+    assert_eq!(current[0]["status"], "Discharging");
+    assert_eq!(
+        request(&sandbox, r#"{"request":"last","count":2}"#).len(),
+        2
+    );
+    assert!(request(&sandbox, r#"{"request":"nope"}"#)[0]["error"].is_string());
+
+    assert!(!sandbox.live_log().exists());
+
+    // SIGUSR1, sent by the system-sleep hook, checkpoints the buffer into
+    // the archive and then counts the checkpoint for the hook to wait on.
+    let count_path = sandbox.path("run/watt-monitor/checkpoints");
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGUSR1);
+    }
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while fs::read_to_string(&count_path).unwrap_or_default() != "1" {
+        assert!(Instant::now() < deadline, "daemon did not checkpoint");
+        thread::sleep(Duration::from_millis(100));
+    }
+    let archive = fs::read_dir(sandbox.path("data/watt-monitor"))
+        .unwrap()
+        .flatten()
+        .next()
+        .unwrap()
+        .path();
+    let archived_rows = || fs::read_to_string(&archive).unwrap().lines().count();
+    let checkpointed = archived_rows();
+    assert!(checkpointed > 3);
+
+    // Shutdown flushes the rest of the buffer before archiving.
+    thread::sleep(Duration::from_millis(1500));
+    stop(child);
+    assert!(archived_rows() > checkpointed);
+    assert!(
+        fs::read_to_string(&archive)
+            .unwrap()
+            .contains(",Discharging,50,5.00,")
+    );
+    assert!(!socket.exists());
+}
+
+#[test]
+fn falls_back_to_private_temp_dir_without_runtime_dir() {
+    let sandbox = Sandbox::new();
+    sandbox.add_discharging_battery();
+    sandbox.write_config("[daemon]\nlog_interval_secs = 1\nflush_interval_secs = 0\n");
+
+    let runtime_dir = sandbox.path(&format!("tmp/watt-monitor-{}", unsafe { libc::geteuid() }));
+    fs::create_dir_all(&runtime_dir).unwrap();
+    fs::set_permissions(&runtime_dir, fs::Permissions::from_mode(0o777)).unwrap();
+
+    // A live log left in the temp directory by an older version.
+    let legacy = "2024-03-05 10:00:00,Discharging,70,5.00";
+    fs::write(
+        sandbox.path("tmp/battery_watt_history.csv"),
+        format!("Time,Status,Capacity(%),Power(W)\n{}\n", legacy),
+    )
+    .unwrap();
+
+    let child = sandbox
+        .daemon()
+        .env_remove("XDG_RUNTIME_DIR")
+        .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+        .spawn()
+        .unwrap();
+    let lines = wait_for_rows(&runtime_dir.join("battery_watt_history.csv"), 2);
+    stop(child);
+
+    assert!(lines[2].contains(",Discharging,50,5.00,"));
+    let mode = fs::metadata(&runtime_dir).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o700);
+
+    assert!(!sandbox.path("tmp/battery_watt_history.csv").exists());
+    let archive = fs::read_to_string(sandbox.path("data/watt-monitor/2024-03-05.csv")).unwrap();
+    assert!(archive.contains(legacy));
+}
+
+#[test]
+fn locks_single_instance_and_reports_status() {
+    let sandbox = Sandbox::new();
+    sandbox.add_discharging_battery();
+    sandbox.write_config("[daemon]\nlog_interval_secs = 1\nflush_interval_secs = 0\n");
+
+    // A PID file left by a crash, naming a process that is still alive.
+    let pid_file = sandbox.path("run/watt-monitor/daemon.pid");
+    fs::create_dir_all(pid_file.parent().unwrap()).unwrap();
+    fs::write(&pid_file, std::process::id().to_string()).unwrap();
+
+    let status = |sandbox: &Sandbox| {
+        sandbox
+            .daemon()
+            .arg("--status")
+            .stdout(Stdio::piped())
+            .output()
+            .unwrap()
+    };
+    assert_eq!(status(&sandbox).status.code(), Some(3));
+
+    let mut child = sandbox
+        .daemon()
+        .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+        .spawn()
+        .unwrap();
+    wait_for_rows(&sandbox.live_log(), 2);
+
+    let second = sandbox
+        .daemon()
+        .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+        .status()
+        .unwrap();
+    assert!(!second.success());
+
+    let output = status(&sandbox);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(&format!("Daemon is running (PID {})", child.id())));
+    assert!(stdout.contains("BAT0 Discharging, 50%, 5.00 W"));
+
+    let stopped = sandbox.daemon().arg("--stop").status().unwrap();
+    assert!(stopped.success());
+    assert!(child.try_wait().unwrap().is_some_and(|s| s.success()));
+    assert_eq!(status(&sandbox).status.code(), Some(3));
+}
+
+#[test]
+fn notifies_service_manager() {
+    let sandbox = Sandbox::new();
+    sandbox.add_discharging_battery();
+
+    // Stands in for systemd's notification socket.
+    let notify_path = sandbox.path("run/notify");
+    let notify_socket = UnixDatagram::bind(&notify_path).unwrap();
+    notify_socket
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let receive = || {
+        let mut message = [0; 256];
+        let len = notify_socket.recv(&mut message).unwrap();
+        String::from_utf8(message[..len].to_vec()).unwrap()
+    };
+
+    // The watchdog expects a ping every second, well within the sampling
+    // interval of four seconds.
+    let child = sandbox
+        .daemon()
+        .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+        .env("NOTIFY_SOCKET", &notify_path)
+        .env("WATCHDOG_USEC", "2000000")
+        .env_remove("WATCHDOG_PID")
+        .spawn()
+        .unwrap();
+
+    assert_eq!(receive(), "READY=1\nSTATUS=Logging BAT0");
+    let started = Instant::now();
+    assert_eq!(receive(), "WATCHDOG=1");
+    assert_eq!(receive(), "WATCHDOG=1");
+    assert!(started.elapsed() < Duration::from_secs(3));
+    while !receive().starts_with("STATUS=Last sample at") {}
+
+    stop(child);
+    notify_socket
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    let mut messages = Vec::new();
+    let mut message = [0; 256];
+    while let Ok(len) = notify_socket.recv(&mut message) {
+        messages.push(String::from_utf8(message[..len].to_vec()).unwrap());
+    }
+    assert_eq!(messages.last().unwrap(), "STOPPING=1");
+}
+
+#[test]
+fn escalates_repeated_read_failures_in_journal_format() {
+    let sandbox = Sandbox::new();
+    sandbox.add_supply(
+        "BAT0",
+        &[
+            ("type", "Battery"),
+            ("status", "Discharging"),
+            ("capacity", "unknown"),
+        ],
+    );
+
+    // systemd exports the identity of the journal stream it connects stderr to.
+    let stderr_path = sandbox.path("stderr");
+    let stderr = fs::File::create(&stderr_path).unwrap();
+    let metadata = stderr.metadata().unwrap();
+    let child = sandbox
+        .daemon()
+        .args(["--interval", "1"])
+        .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+        .env(
+            "JOURNAL_STREAM",
+            format!("{}:{}", metadata.dev(), metadata.ino()),
+        )
+        .stderr(stderr)
+        .spawn()
+        .unwrap();
+
+    let read_log = || fs::read_to_string(&stderr_path).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !read_log().contains("times in a row") {
+        assert!(Instant::now() < deadline, "failures were not escalated");
+        thread::sleep(Duration::from_millis(100));
+    }
+    fs::write(sandbox.path("sys/class/power_supply/BAT0/capacity"), "50\n").unwrap();
+    while !read_log().contains("again after") {
+        assert!(Instant::now() < deadline, "recovery was not logged");
+        thread::sleep(Duration::from_millis(100));
+    }
+    stop(child);
+
+    let log = read_log();
+    let warnings = log
+        .lines()
+        .filter(|l| l.starts_with("<4>Failed to read battery info for BAT0:"))
+        .count();
+    assert_eq!(warnings, 4);
+    let errors: Vec<&str> = log.lines().filter(|l| l.starts_with("<3>")).collect();
+    assert_eq!(errors.len(), 1, "{}", log);
+    assert!(errors[0].starts_with("<3>Failed to read battery info for BAT0 5 times in a row"));
+    assert!(
+        log.lines()
+            .any(|l| l.starts_with("<6>Read battery info for BAT0 again after"))
+    );
+    // Debug messages stay hidden at the default level.
+    assert!(
+        log.lines()
+            .all(|l| l.starts_with('<') && !l.starts_with("<7>"))
+    );
+}
+
+#[test]
+fn records_battery_health_once_a_day() {
+    let sandbox = Sandbox::new();
+    sandbox.add_supply(
+        "BAT0",
+        &[
+            ("type", "Battery"),
+            ("status", "Discharging"),
+            ("capacity", "50"),
+            ("power_now", "5000000"),
+            ("energy_full", "51300000"),
+            ("energy_full_design", "57000000"),
+            ("cycle_count", "123"),
+        ],
+    );
+    sandbox.write_config("[daemon]\nlog_interval_secs = 1\nflush_interval_secs = 0\n");
+    let health_log = sandbox.path("data/watt-monitor/health.csv");
+
+    // A restart on the same day with unchanged readings adds no row.
+    for _ in 0..2 {
+        let child = sandbox
+            .daemon()
+            .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+            .spawn()
+            .unwrap();
+        wait_for_rows(&sandbox.live_log(), 2);
+        stop(child);
+    }
+
+    let lines = wait_for_rows(&health_log, 1);
+    assert_eq!(lines.len(), 2, "{:?}", lines);
+    assert_eq!(
+        lines[0],
+        "Date,Battery,EnergyFull(Wh),EnergyFullDesign(Wh),ChargeFull(Ah),ChargeFullDesign(Ah),CycleCount"
+    );
+    assert!(lines[1].ends_with(",BAT0,51.3,57.0,,,123"), "{}", lines[1]);
+
+    let output = sandbox
+        .command("health")
+        .stdout(Stdio::piped())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout
+            .lines()
+            .any(|l| l.contains("BAT0") && l.contains("51.30 Wh") && l.contains("90.0%")),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn logs_battery_and_cpu_temperature() {
+    let sandbox = Sandbox::new();
+    sandbox.add_supply(
+        "BAT0",
+        &[
+            ("type", "Battery"),
+            ("status", "Discharging"),
+            ("capacity", "50"),
+            ("power_now", "5000000"),
+            ("temp", "315"),
+        ],
+    );
+    let hwmon = sandbox.path("sys/class/hwmon/hwmon3");
+    fs::create_dir_all(&hwmon).unwrap();
+    for (attr, value) in [
+        ("name", "coretemp"),
+        ("temp1_label", "Core 0"),
+        ("temp1_input", "48000"),
+        ("temp2_label", "Package id 0"),
+        ("temp2_input", "52000"),
+    ] {
+        fs::write(hwmon.join(attr), format!("{}\n", value)).unwrap();
+    }
+    sandbox.write_config(
+        "[daemon]\n\
+         log_interval_secs = 1\n\
+         flush_interval_secs = 0\n\
+         battery_temperature = true\n\
+         cpu_sensor = \"coretemp/Package id 0\"\n",
+    );
+
+    let child = sandbox
+        .daemon()
+        .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+        .spawn()
+        .unwrap();
+    let lines = wait_for_rows(&sandbox.live_log(), 2);
+    stop(child);
+
+    let header = lines[0].as_str();
+    assert_eq!(field(header, &lines[1], "Temp(C)"), "");
+    assert_eq!(field(header, &lines[2], "Temp(C)"), "31.5");
+    assert_eq!(field(header, &lines[2], "CpuTemp(C)"), "52.0");
+}
+
+#[test]
+fn logs_rapl_power_across_counter_wraparound() {
+    let sandbox = Sandbox::new();
+    sandbox.add_supply(
+        "BAT0",
+        &[
+            ("type", "Battery"),
+            ("status", "Full"),
+            ("capacity", "100"),
+            ("power_now", "0"),
+        ],
+    );
+    let powercap = sandbox.path("sys/class/powercap");
+    let package = powercap.join("intel-rapl:0");
+    let dram = powercap.join("intel-rapl:0:2");
+    for (zone, name, energy) in [(&package, "package-0", "999000000"), (&dram, "dram", "0")] {
+        fs::create_dir_all(zone).unwrap();
+        fs::write(zone.join("name"), format!("{}\n", name)).unwrap();
+        fs::write(zone.join("energy_uj"), format!("{}\n", energy)).unwrap();
+        fs::write(zone.join("max_energy_range_uj"), "1000000000\n").unwrap();
+    }
+    fs::create_dir_all(powercap.join("intel-rapl")).unwrap();
+    sandbox.write_config("[daemon]\nflush_interval_secs = 0\nrapl = true\n");
+
+    let child = sandbox
+        .daemon()
+        .args(["--interval", "1"])
+        .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+        .spawn()
+        .unwrap();
+    wait_for_rows(&sandbox.live_log(), 1);
+    // 5 J of package energy across the wraparound of the counter.
+    fs::write(package.join("energy_uj"), "4000000\n").unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let (header, row) = loop {
+        let lines = wait_for_rows(&sandbox.live_log(), 2);
+        let header = lines[0].clone();
+        if let Some(row) = lines[2..].iter().find(|row| {
+            field(&header, row, "RaplPackage(W)")
+                .parse::<f64>()
+                .unwrap_or(0.0)
+                > 0.0
+        }) {
+            break (header, row.clone());
+        }
+        assert!(Instant::now() < deadline, "no package power logged");
+        thread::sleep(Duration::from_millis(100));
+    };
+    stop(child);
+
+    let watts: f64 = field(&header, &row, "RaplPackage(W)").parse().unwrap();
+    assert!((2.0..=6.0).contains(&watts), "{}", row);
+    assert_eq!(field(&header, &row, "RaplDram(W)"), "0.00");
+    assert_eq!(field(&header, &row, "RaplCore(W)"), "");
+}
+
+#[test]
+fn attributes_power_to_processes_by_cpu_time() {
+    let sandbox = Sandbox::new();
+    sandbox.add_supply(
+        "BAT0",
+        &[
+            ("type", "Battery"),
+            ("status", "Discharging"),
+            ("capacity", "50"),
+            ("power_now", "10000000"),
+        ],
+    );
+    let proc_root = sandbox.path("proc");
+    let write_stat = |pid: u32, command: &str, ticks: u64| {
+        let dir = proc_root.join(pid.to_string());
+        fs::create_dir_all(&dir).unwrap();
+        let stat = format!(
+            "{} ({}) S 1 {} {} 0 -1 4194304 0 0 0 0 {} {} 0 0 20 0 1 0 5000 0 0",
+            pid, command, pid, pid, ticks, ticks
+        );
+        // Replaced atomically so that the daemon never reads a partial file.
+        fs::write(dir.join("stat.tmp"), stat).unwrap();
+        fs::rename(dir.join("stat.tmp"), dir.join("stat")).unwrap();
+    };
+    write_stat(100, "(busy) worker", 0);
+    write_stat(200, "idle", 0);
+    sandbox.write_config(&format!(
+        "[daemon]\n\
+         flush_interval_secs = 0\n\
+         processes = true\n\
+         process_interval_secs = 2\n\
+         proc_root = {:?}\n",
+        proc_root
+    ));
+
+    let child = sandbox
+        .daemon()
+        .args(["--interval", "1"])
+        .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+        .spawn()
+        .unwrap();
+
+    // The busy process uses three times the CPU time of the idle one.
+    let data_dir = sandbox.path("data/watt-monitor");
+    let process_log = || {
+        fs::read_dir(&data_dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .find(|path| path.to_string_lossy().contains("processes-"))
+    };
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut ticks = 0;
+    let lines = loop {
+        ticks += 2;
+        write_stat(100, "(busy) worker", 3 * ticks);
+        write_stat(200, "idle", ticks);
+        if let Some(path) = process_log() {
+            let lines: Vec<String> = fs::read_to_string(path)
+                .unwrap()
+                .lines()
+                .map(str::to_string)
+                .collect();
+            if lines.len() >= 3 {
+                break lines;
+            }
+        }
+        assert!(Instant::now() < deadline, "no process log written");
+        thread::sleep(Duration::from_millis(100));
+    };
+    stop(child);
+
+    let header = lines[0].as_str();
+    assert_eq!(header, "Time,Window(s),Pid,Command,CpuTime(s),Energy(J)");
+    let busy = lines[1].as_str();
+    let idle = lines[2].as_str();
+    assert!(busy.contains(",100,(busy) worker,"), "{}", busy);
+    assert!(idle.contains(",200,idle,"), "{}", idle);
+    let energy = |row: &str| -> f64 { row.rsplit(',').next().unwrap().parse().unwrap() };
+    assert!(energy(busy) > 2.0 * energy(idle), "{:?}", lines);
+    assert!(energy(idle) > 0.0);
+}
+
+#[test]
+fn reads_boot_id_from_proc_root() {
+    let sandbox = Sandbox::new();
+    sandbox.add_supply(
+        "BAT0",
+        &[
+            ("type", "Battery"),
+            ("status", "Discharging"),
+            ("capacity", "50"),
+            ("power_now", "5000000"),
+        ],
+    );
+    let proc_root = sandbox.path("proc");
+    let boot_id = "0f9c2b1e-6d4a-4c8e-9b7a-3e5d1f2a4c6b";
+    fs::create_dir_all(proc_root.join("sys/kernel/random")).unwrap();
+    fs::write(
+        proc_root.join("sys/kernel/random/boot_id"),
+        format!("{}\n", boot_id),
+    )
+    .unwrap();
+    sandbox.write_config(&format!(
+        "[daemon]\n\
+         log_interval_secs = 1\n\
+         flush_interval_secs = 0\n\
+         proc_root = {:?}\n",
+        proc_root
+    ));
+
+    let child = sandbox
+        .daemon()
+        .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+        .spawn()
+        .unwrap();
+    let lines = wait_for_rows(&sandbox.live_log(), 2);
+    stop(child);
+
+    let header = lines[0].as_str();
+    assert_eq!(field(header, &lines[1], "Event"), "start");
+    assert_eq!(field(header, &lines[1], "BootId"), boot_id);
+    assert_eq!(field(header, &lines[2], "BootId"), boot_id);
+}
+
+#[test]
+fn logs_backlight_cpu_frequency_and_load() {
+    let sandbox = Sandbox::new();
+    sandbox.add_discharging_battery();
+    let backlight = sandbox.path("sys/class/backlight/intel_backlight");
+    fs::create_dir_all(&backlight).unwrap();
+    fs::write(backlight.join("brightness"), "300\n").unwrap();
+    fs::write(backlight.join("max_brightness"), "1200\n").unwrap();
+    for (cpu, khz) in [("cpu0", "1800000"), ("cpu1", "2200000")] {
+        let cpufreq = sandbox
+            .path("sys/devices/system/cpu")
+            .join(cpu)
+            .join("cpufreq");
+        fs::create_dir_all(&cpufreq).unwrap();
+        fs::write(cpufreq.join("scaling_cur_freq"), format!("{}\n", khz)).unwrap();
+    }
+    // Not a CPU, despite the prefix.
+    fs::create_dir_all(sandbox.path("sys/devices/system/cpu/cpufreq")).unwrap();
+    let proc_root = sandbox.path("proc");
+    fs::create_dir_all(&proc_root).unwrap();
+    fs::write(proc_root.join("loadavg"), "1.25 0.80 0.40 2/345 6789\n").unwrap();
+    sandbox.write_config(&format!(
+        "[daemon]\n\
+         log_interval_secs = 1\n\
+         flush_interval_secs = 0\n\
+         system_state = true\n\
+         proc_root = {:?}\n",
+        proc_root
+    ));
+
+    let child = sandbox
+        .daemon()
+        .env("WATT_MONITOR_SYSFS", sandbox.path("sys"))
+        .spawn()
+        .unwrap();
+    let lines = wait_for_rows(&sandbox.live_log(), 2);
+    stop(child);
+
+    let header = lines[0].as_str();
+    let row = lines[2].as_str();
+    assert_eq!(field(header, row, "Brightness(%)"), "25");
+    assert_eq!(field(header, row, "CpuFreq(MHz)"), "2000");
+    assert_eq!(field(header, row, "Load1"), "1.25");
+}